@@ -8,6 +8,7 @@
 /// Examples:
 ///  * `width 120` - Set width to 120
 ///  * `width` - Print the current width
+///  * `paddle.color red` - Set a property of a nested config
 ///  * `reset width` - Reset width to its default value (100)
 ///  * `find a` - Find all commands with `a` in their name
 ///  * `reset` - Reset all variables to their defaults
@@ -19,7 +20,10 @@ use amethyst::{
     utils::application_root_dir,
 };
 
-use amethyst_console::{amethyst_imgui::RenderImgui, IConsoleExt, IVisitExt};
+use amethyst_console::{
+    amethyst_imgui::RenderImgui, ClampedProperty, EnumProperty, IConsoleExt, IVisitExt, ListExt,
+    ResultAction,
+};
 
 pub struct ArenaConfig {
     pub height: f32,
@@ -56,6 +60,12 @@ impl IVisitExt for ArenaConfig {
             &mut self.height,
             default.height,
         ));
+
+        // Simple queries can return their output instead of writing to the console
+        let area = self.width * self.height;
+        f(&mut ResultAction("area", "Print the arena area", |_| {
+            area.to_string().into()
+        }));
     }
 }
 
@@ -78,17 +88,21 @@ impl IVisitExt for PaddleConfig {
         _console: &mut dyn IConsoleExt,
     ) {
         let default = Self::default();
-        f(&mut cvar::Property(
+        f(&mut ClampedProperty(
             "velocity",
             "paddle velocity",
             &mut self.velocity,
             default.velocity,
+            0.,
+            10.,
         ));
-        f(&mut cvar::Property(
+        // Only accepts one of the listed colors, which tab completion will offer
+        f(&mut EnumProperty(
             "color",
             "paddle color",
             &mut self.color,
-            default.color,
+            "white",
+            &["white", "red", "green", "blue"],
         ));
     }
 }
@@ -100,7 +114,9 @@ pub fn color_test(console: &mut dyn IConsoleExt) {
             for b in (0..=2).rev() {
                 console.write_colored(
                     [(r as f32) / 2., (g as f32) / 2., (b as f32) / 2., 1.],
-                    " ■",
+                    // U+25A0, a filled square. The default imgui font has no glyph for it, so
+                    // load one that does or it is drawn as `?`
+                    " \u{25a0}",
                 );
             }
         }
@@ -128,7 +144,13 @@ impl IVisitExt for GameConfig {
         // Calls to children will add their entries the the available command list.
         // This allows for deeply nested data structures.
         self.arena.visit_mut_ext(f, console);
-        self.paddle.visit_mut_ext(f, console);
+        // Or keep a child's entries under its own name, as `paddle.color`
+        f(&mut ListExt(
+            "paddle",
+            "Paddle settings",
+            &mut self.paddle,
+            console,
+        ));
     }
 }
 
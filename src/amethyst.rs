@@ -1,17 +1,148 @@
 pub use amethyst_imgui;
 
-use crate::{ConsoleWindow, IVisitExt, VisitMutExt};
+use crate::{join_command, ConsoleError, ConsoleWindow, IConsoleExt, IVisitExt, VisitMutExt};
 use amethyst::{
     core::{
         shrev::{EventChannel, ReaderId},
         SystemDesc,
     },
-    ecs::{Read, System, Write},
-    input::{InputEvent, StringBindings},
+    ecs::{Read, System, SystemData, Write},
+    input::{InputEvent, StringBindings, VirtualKeyCode},
     prelude::*,
 };
 use imgui::im_str;
+use std::collections::{HashMap, VecDeque};
 use std::marker::PhantomData;
+use std::path::PathBuf;
+
+/// Input action which shows or hides the console, unless changed with `with_toggle_action`.
+const DEFAULT_TOGGLE_ACTION: &str = "toggle_console";
+
+/// How many input events `inputdebug` remembers.
+const INPUT_HISTORY: usize = 32;
+
+/// Sent on an `EventChannel<ConsoleEvent>` whenever the console is shown or hidden.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsoleEvent {
+    Opened,
+    Closed,
+}
+
+/// Sent on an `EventChannel<ConsoleCommandEvent>` for every command line the console runs,
+/// including those received over TCP with `with_remote`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleCommandEvent {
+    pub line: String,
+    pub success: bool,
+}
+
+/// Sent on an `EventChannel<ConsoleChangeEvent>` for every property a command changes.
+///
+/// Read it to react straight away, such as rebuilding a mesh when its size is set, rather than
+/// comparing the config every frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleChangeEvent {
+    pub path: String,
+    pub value: String,
+}
+
+/// Resource other systems can read to see whether the console is shown.
+///
+/// Kept up to date by `ConsoleSystem` every frame. Useful for ignoring game controls while the
+/// console is up.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ConsoleState {
+    pub open: bool,
+    /// The prompt has focus, so key presses are meant for it rather than the game.
+    ///
+    /// Input events are still sent to every reader, so game systems have to check this
+    /// themselves. Always false if capture was turned off with `with_input_capture`.
+    pub input_captured: bool,
+}
+
+/// Resource other systems can use to run console commands.
+///
+/// Commands pushed here are queued on the console the next time `ConsoleSystem` runs.
+#[derive(Debug, Default)]
+pub struct ConsoleCommands {
+    pending: Vec<String>,
+}
+
+impl ConsoleCommands {
+    pub fn push<S: Into<String>>(&mut self, cmd: S) {
+        self.pending.push(cmd.into());
+    }
+}
+
+type Callback = Box<dyn FnMut() + Send + Sync>;
+
+/// The configs a `ConsoleSystem` edits, each kept as its own resource in the world.
+///
+/// Implemented for any single config, and for tuples of up to six of them, so that one console
+/// reaches several resources: `create_system::<(ArenaConfig, PaddleConfig)>()`. Their entries
+/// are listed together, so either give them distinct names or nest each under a `ListExt`.
+pub trait ConsoleResources<'s> {
+    type Data: SystemData<'s>;
+
+    /// Add every resource to the world, at its default value.
+    fn insert_defaults(world: &mut World);
+
+    fn visit(
+        data: &mut Self::Data,
+        f: &mut dyn FnMut(&mut dyn cvar::INode),
+        console: &mut dyn IConsoleExt,
+    );
+}
+
+impl<'s, T> ConsoleResources<'s> for T
+where
+    T: 'static + Send + Sync + Default + IVisitExt,
+{
+    type Data = Write<'s, T>;
+
+    fn insert_defaults(world: &mut World) {
+        world.insert(T::default());
+    }
+
+    fn visit(
+        data: &mut Self::Data,
+        f: &mut dyn FnMut(&mut dyn cvar::INode),
+        console: &mut dyn IConsoleExt,
+    ) {
+        data.visit_mut_ext(f, console);
+    }
+}
+
+macro_rules! impl_console_resources {
+    ($($name:ident),+) => {
+        impl<'s, $($name),+> ConsoleResources<'s> for ($($name,)+)
+        where
+            $($name: 'static + Send + Sync + Default + IVisitExt),+
+        {
+            type Data = ($(Write<'s, $name>,)+);
+
+            fn insert_defaults(world: &mut World) {
+                $(world.insert($name::default());)+
+            }
+
+            #[allow(non_snake_case)]
+            fn visit(
+                data: &mut Self::Data,
+                f: &mut dyn FnMut(&mut dyn cvar::INode),
+                console: &mut dyn IConsoleExt,
+            ) {
+                let ($($name,)+) = data;
+                $($name.visit_mut_ext(f, console);)+
+            }
+        }
+    };
+}
+
+impl_console_resources!(A, B);
+impl_console_resources!(A, B, C);
+impl_console_resources!(A, B, C, D);
+impl_console_resources!(A, B, C, D, E);
+impl_console_resources!(A, B, C, D, E, F);
 
 /// Amethyst system to manage configuration updates, and console window rendering
 ///
@@ -21,79 +152,360 @@ pub struct ConsoleSystem<T> {
     open: bool,
     console: ConsoleWindow,
     event_reader: Option<ReaderId<InputEvent<StringBindings>>>,
+    on_open: Option<Callback>,
+    on_close: Option<Callback>,
+    recent_events: VecDeque<String>,
+    history_path: Option<PathBuf>,
+    toggle_action: String,
+    toggle_key: Option<VirtualKeyCode>,
+    capture_input: bool,
+    /// Commands to run for each bound key or action, by lowercase name.
+    bindings: HashMap<String, String>,
+    /// Those set up with `with_binding`, which `console_reset` goes back to.
+    default_bindings: HashMap<String, String>,
+    #[cfg(feature = "remote")]
+    remote: Option<crate::RemoteConsole>,
     _marker: PhantomData<T>,
 }
 
 impl<T> ConsoleSystem<T> {
-    pub fn new(console: ConsoleWindow) -> ConsoleSystem<T> {
+    pub fn new(mut console: ConsoleWindow) -> ConsoleSystem<T> {
+        console.set_record_executed(true);
+        console.set_record_changes(true);
         ConsoleSystem {
             open: true,
             console,
             event_reader: None,
+            on_open: None,
+            on_close: None,
+            recent_events: VecDeque::new(),
+            history_path: None,
+            toggle_action: DEFAULT_TOGGLE_ACTION.to_string(),
+            toggle_key: None,
+            capture_input: true,
+            bindings: HashMap::new(),
+            default_bindings: HashMap::new(),
+            #[cfg(feature = "remote")]
+            remote: None,
             _marker: PhantomData,
         }
     }
+
+    /// The console window, for writing output or queueing commands.
+    pub fn console(&mut self) -> &mut ConsoleWindow {
+        &mut self.console
+    }
+
+    /// Run `f` every time the console is opened.
+    ///
+    /// Read `EventChannel<ConsoleEvent>` instead if you would rather react from another system.
+    pub fn on_open<F>(mut self, f: F) -> ConsoleSystem<T>
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.on_open = Some(Box::new(f));
+        self
+    }
+
+    /// Run `f` every time the console is closed.
+    pub fn on_close<F>(mut self, f: F) -> ConsoleSystem<T>
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.on_close = Some(Box::new(f));
+        self
+    }
+
+    /// Show and hide the console with the `action` input action, instead of `toggle_console`.
+    pub fn with_toggle_action<S: Into<String>>(mut self, action: S) -> ConsoleSystem<T> {
+        self.toggle_action = action.into();
+        self
+    }
+
+    /// Also show and hide the console whenever `key` is pressed, without needing an input action.
+    ///
+    /// The toggle action keeps working alongside it.
+    pub fn with_toggle_key(mut self, key: VirtualKeyCode) -> ConsoleSystem<T> {
+        self.toggle_key = Some(key);
+        self
+    }
+
+    /// Whether to set `ConsoleState::input_captured` while typing in the console. On by default.
+    ///
+    /// Turn it off for games that want input to pass through to them regardless.
+    pub fn with_input_capture(mut self, capture: bool) -> ConsoleSystem<T> {
+        self.capture_input = capture;
+        self
+    }
+
+    /// Run `cmd` whenever `key` is pressed, open or not. The same as typing `bind <key> <cmd>`.
+    ///
+    /// `key` is either a key name such as `F5`, or the name of an input action. Bindings are left
+    /// alone while typing in the prompt, so the key can still be typed.
+    pub fn with_binding(mut self, key: &str, cmd: &str) -> ConsoleSystem<T> {
+        self.bindings.insert(key.to_lowercase(), cmd.to_string());
+        self.default_bindings = self.bindings.clone();
+        self
+    }
+
+    /// Also run commands received by `remote` every frame, whether the console is open or not.
+    #[cfg(feature = "remote")]
+    pub fn with_remote(mut self, remote: crate::RemoteConsole) -> ConsoleSystem<T> {
+        self.remote = Some(remote);
+        self
+    }
+
+    /// Load command history from `path` now, and save it back when the system is dropped.
+    ///
+    /// A missing file is fine, it will be created on the first save.
+    pub fn with_history<P: Into<PathBuf>>(mut self, path: P) -> ConsoleSystem<T> {
+        let path = path.into();
+        if let Err(e) = self.console.load_history(&path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Could not load console history from {:?}: {}", path, e);
+            }
+        }
+        self.history_path = Some(path);
+        self
+    }
+}
+
+impl<T> Drop for ConsoleSystem<T> {
+    fn drop(&mut self) {
+        if let Some(path) = &self.history_path {
+            if let Err(e) = self.console.save_history(path) {
+                log::warn!("Could not save console history to {:?}: {}", path, e);
+            }
+        }
+    }
 }
 
 impl<'a, 'b, T> SystemDesc<'a, 'b, ConsoleSystem<T>> for ConsoleSystem<T>
 where
-    T: 'static + std::marker::Send + std::marker::Sync + std::default::Default + IVisitExt,
+    T: for<'s> ConsoleResources<'s>,
 {
-    fn build(self, world: &mut World) -> ConsoleSystem<T> {
-        world.insert(T::default());
+    fn build(mut self, world: &mut World) -> ConsoleSystem<T> {
+        <T as ConsoleResources<'_>>::insert_defaults(world);
         world.setup::<Read<EventChannel<InputEvent<StringBindings>>>>();
+        world.setup::<Write<EventChannel<ConsoleEvent>>>();
+        world.setup::<Write<EventChannel<ConsoleCommandEvent>>>();
+        world.setup::<Write<EventChannel<ConsoleChangeEvent>>>();
+        world.setup::<Write<ConsoleCommands>>();
+        world.insert(ConsoleState {
+            open: self.open,
+            input_captured: false,
+        });
         let event_reader = world
             .fetch_mut::<EventChannel<InputEvent<StringBindings>>>()
             .register_reader();
-        ConsoleSystem {
-            open: self.open,
-            console: self.console,
-            event_reader: Some(event_reader),
-            _marker: PhantomData,
+        self.event_reader = Some(event_reader);
+        self
+    }
+}
+
+impl<T> ConsoleSystem<T> {
+    /// The command bound to the key or action `event` presses, if any. None while the prompt has
+    /// focus, as the key is being typed.
+    pub(crate) fn bound_command(&self, event: &InputEvent<StringBindings>) -> Option<String> {
+        if self.open && self.console.has_focus() {
+            return None;
         }
+        let pressed = match event {
+            InputEvent::ActionPressed(action) => action.to_lowercase(),
+            InputEvent::KeyPressed { key_code, .. } => format!("{:?}", key_code).to_lowercase(),
+            _ => return None,
+        };
+        self.bindings.get(&pressed).cloned()
     }
 }
 
 impl<'s, T> System<'s> for ConsoleSystem<T>
 where
-    T: 'static + std::marker::Send + std::marker::Sync + std::default::Default + IVisitExt,
+    T: ConsoleResources<'s>,
 {
     type SystemData = (
         Read<'s, EventChannel<InputEvent<StringBindings>>>,
-        Write<'s, T>,
+        Write<'s, EventChannel<ConsoleEvent>>,
+        Write<'s, EventChannel<ConsoleCommandEvent>>,
+        Write<'s, EventChannel<ConsoleChangeEvent>>,
+        Write<'s, ConsoleCommands>,
+        Write<'s, ConsoleState>,
+        T::Data,
     );
 
-    fn run(&mut self, (events, mut config): Self::SystemData) {
+    fn run(
+        &mut self,
+        (
+            events,
+            mut console_events,
+            mut command_events,
+            mut change_events,
+            mut commands,
+            mut state,
+            mut config,
+        ): Self::SystemData,
+    ) {
+        let was_open = self.open;
+        for cmd in commands.pending.drain(..) {
+            self.console.queue_command(cmd);
+        }
         if let Some(reader) = &mut self.event_reader {
             for event in events.read(reader) {
-                if let InputEvent::ActionPressed(s) = event {
-                    match s.as_ref() {
-                        "toggle_console" => {
-                            self.open = !self.open;
-                        }
-                        _ => {}
+                if let InputEvent::MouseMoved { .. } = event {
+                    // far too noisy to be useful here
+                } else {
+                    if self.recent_events.len() == INPUT_HISTORY {
+                        self.recent_events.pop_front();
                     }
+                    self.recent_events.push_back(format!("{:?}", event));
+                }
+                let toggle = match event {
+                    InputEvent::ActionPressed(s) => *s == self.toggle_action,
+                    InputEvent::KeyPressed { key_code, .. } => Some(*key_code) == self.toggle_key,
+                    _ => false,
+                };
+                if toggle {
+                    self.open = !self.open;
+                }
+
+                if let Some(cmd) = self.bound_command(event) {
+                    self.console.queue_command(cmd);
                 }
             }
         }
 
+        let recent_events = &self.recent_events;
+        let toggle_action = &self.toggle_action;
+        let toggle_key = self.toggle_key;
+        let bindings = &mut self.bindings;
         let mut root = VisitMutExt(move |f, console| {
-            config.visit_mut_ext(f, console);
+            T::visit(&mut config, f, console);
+            f(&mut cvar::Action(
+                "inputdebug",
+                "Show recent input events and the action used to toggle the console",
+                |_, _| {
+                    let mut out = format!("Toggle action: {}\n", toggle_action);
+                    if let Some(key) = toggle_key {
+                        out.push_str(&format!("Toggle key: {:?}\n", key));
+                    }
+                    if recent_events.is_empty() {
+                        out.push_str("No input events seen yet");
+                    }
+                    for event in recent_events {
+                        out.push_str(&format!("\t{}\n", event));
+                    }
+                    console.write_result(out.trim_end().to_string().into());
+                },
+            ));
+            f(&mut cvar::Action(
+                "bind",
+                "<key> <commands>\nRun commands whenever a key (such as F5) or input action is pressed",
+                |args, _| match args.get(0) {
+                    Some(key) if args.len() > 1 => {
+                        bindings.insert(key.to_lowercase(), join_command(&args[1..]));
+                    }
+                    _ => console.write_result(
+                        ConsoleError::InvalidUsage("bind <key> <commands>".to_string()).into(),
+                    ),
+                },
+            ));
+            f(&mut cvar::Action(
+                "unbind",
+                "<key>\nRemove a binding made with bind",
+                |args, _| match args.get(0) {
+                    Some(key) => {
+                        if bindings.remove(&key.to_lowercase()).is_none() {
+                            let e = ConsoleError::InvalidValue(format!("{} is not bound", key));
+                            console.write_result(e.into());
+                        }
+                    }
+                    None => console.write_result(
+                        ConsoleError::InvalidUsage("unbind <key>".to_string()).into(),
+                    ),
+                },
+            ));
+            f(&mut cvar::Action(
+                "binds",
+                "List every key binding",
+                |_, _| {
+                    let mut binds = bindings.iter().collect::<Vec<_>>();
+                    binds.sort();
+                    let out = if binds.is_empty() {
+                        "No bindings".to_string()
+                    } else {
+                        binds
+                            .iter()
+                            .map(|(key, cmd)| format!("{} = {}", key, cmd))
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    };
+                    console.write_result(out.into());
+                },
+            ));
         });
 
         let open = self.open;
+        if !open {
+            // build takes care of this while the window is shown
+            self.console.run_queued(&mut root);
+        }
+        let (console, opened) = (&mut self.console, &mut self.open);
         amethyst_imgui::with(|ui| {
-            let window = imgui::Window::new(im_str!("Console")).opened(&mut self.open);
+            let window = imgui::Window::new(im_str!("Console")).opened(opened);
             if open {
-                let console = &mut self.console;
                 console.build(ui, window, &mut root);
             }
         });
+
+        #[cfg(feature = "remote")]
+        {
+            if let Some(remote) = &mut self.remote {
+                remote.poll(&mut root);
+                self.console.record_changes(remote.take_changes());
+                for (line, success) in remote.take_executed() {
+                    command_events.single_write(ConsoleCommandEvent { line, success });
+                }
+            }
+        }
+
+        if self.console.take_state_reset() {
+            self.bindings = self.default_bindings.clone();
+            // so a cleared history doesn't come back on the next run
+            if let Some(path) = &self.history_path {
+                if let Err(e) = self.console.save_history(path) {
+                    log::warn!("Could not save console history to {:?}: {}", path, e);
+                }
+            }
+        }
+
+        for (line, success) in self.console.take_executed() {
+            command_events.single_write(ConsoleCommandEvent { line, success });
+        }
+        for (path, value) in self.console.take_changes() {
+            change_events.single_write(ConsoleChangeEvent { path, value });
+        }
+
+        state.open = self.open;
+        state.input_captured = self.capture_input && self.open && self.console.has_focus();
+        if self.open != was_open {
+            let (event, callback) = if self.open {
+                (ConsoleEvent::Opened, &mut self.on_open)
+            } else {
+                (ConsoleEvent::Closed, &mut self.on_close)
+            };
+            if let Some(callback) = callback {
+                callback();
+            }
+            console_events.single_write(event);
+        }
     }
 }
 
 /// Creates a system to manage the given console
 fn init_system<T>(mut console_window: ConsoleWindow) -> ConsoleSystem<T> {
+    if let Ok(root) = amethyst::utils::application_root_dir() {
+        console_window.set_script_dir(root);
+    }
     console_window.write("Type '");
     console_window.write_colored([1., 0., 0., 1.], "HELP");
     console_window.write("' for help, press ");
@@ -108,3 +520,27 @@ pub fn create_system<T>() -> ConsoleSystem<T> {
     let console_window = crate::create_console();
     init_system(console_window)
 }
+
+/// Like `create_system`, but with a console window configured by `ConsoleWindow::builder`.
+pub fn create_system_with_console<T, W: Into<ConsoleWindow>>(console: W) -> ConsoleSystem<T> {
+    init_system(console.into())
+}
+
+/// Like `create_system`, but opened and closed with `action` instead of `toggle_console`.
+pub fn create_system_with_action<T>(action: &str) -> ConsoleSystem<T> {
+    create_system().with_toggle_action(action)
+}
+
+/// Like `create_system`, but also accepting commands over TCP on `addr`, such as `"127.0.0.1:5555"`.
+///
+/// Commands are read without blocking and run against the config from `ConsoleSystem::run`, so
+/// no locking is needed. Anyone who can reach the port can change the config, so only loopback
+/// addresses are accepted; use `RemoteConsole::bind_with_password` and `ConsoleSystem::with_remote`
+/// for any other.
+#[cfg(feature = "remote")]
+pub fn create_system_with_tcp<T, A: std::net::ToSocketAddrs>(
+    addr: A,
+) -> std::io::Result<ConsoleSystem<T>> {
+    let remote = crate::RemoteConsole::bind(addr)?;
+    Ok(create_system().with_remote(remote))
+}
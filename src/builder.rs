@@ -0,0 +1,152 @@
+use crate::{ConsoleWindow, DockEdge};
+use std::time::Duration;
+
+/// Chained configuration for a `ConsoleWindow`.
+///
+/// Each method does the same as the `ConsoleWindow` setter of the same name, so everything here
+/// can still be changed later on. Get one from `ConsoleWindow::builder`.
+///
+/// ```ignore
+/// let console = ConsoleWindow::builder()
+///     .scrollback_limit(5000)
+///     .timestamps(true)
+///     .prompt("$ ", [0., 1., 0., 1.])
+///     .build();
+/// ```
+pub struct ConsoleWindowBuilder {
+    window: ConsoleWindow,
+}
+
+impl ConsoleWindowBuilder {
+    pub fn new() -> ConsoleWindowBuilder {
+        ConsoleWindowBuilder {
+            window: ConsoleWindow::new(),
+        }
+    }
+
+    pub fn scrollback_limit(mut self, limit: usize) -> Self {
+        self.window.set_scrollback_limit(limit);
+        self
+    }
+
+    pub fn history_limit(mut self, limit: usize) -> Self {
+        self.window.set_history_limit(limit);
+        self
+    }
+
+    pub fn wrap(mut self, wrap: bool) -> Self {
+        self.window.set_wrap(wrap);
+        self
+    }
+
+    pub fn timestamps(mut self, timestamps: bool) -> Self {
+        self.window.show_timestamps(timestamps);
+        self
+    }
+
+    pub fn prompt(mut self, label: &str, color: [f32; 4]) -> Self {
+        self.window.set_prompt(label, color);
+        self
+    }
+
+    pub fn prompt_hint(mut self, hint: Option<&str>) -> Self {
+        self.window.set_prompt_hint(hint);
+        self
+    }
+
+    pub fn echo_style(mut self, prefix: Option<&str>, color: [f32; 4]) -> Self {
+        self.window.set_echo_style(prefix, color);
+        self
+    }
+
+    pub fn reset_clears_history(mut self, clear: bool) -> Self {
+        self.window.set_reset_clears_history(clear);
+        self
+    }
+
+    pub fn docked(mut self, edge: DockEdge) -> Self {
+        self.window.set_docked(edge);
+        self
+    }
+
+    pub fn overlay(mut self, position: [f32; 2], size: [f32; 2]) -> Self {
+        self.window.set_overlay(position, size);
+        self
+    }
+
+    pub fn value_display_width(mut self, width: usize) -> Self {
+        self.window.set_value_display_width(width);
+        self
+    }
+
+    pub fn number_grouping(mut self, group: bool) -> Self {
+        self.window.set_number_grouping(group);
+        self
+    }
+
+    pub fn help_page_lines(mut self, lines: usize) -> Self {
+        self.window.set_help_page_lines(lines);
+        self
+    }
+
+    pub fn case_insensitive(mut self, ignore_case: bool) -> Self {
+        self.window.set_case_insensitive(ignore_case);
+        self
+    }
+
+    pub fn fuzzy_matching(mut self, fuzzy: bool) -> Self {
+        self.window.set_fuzzy_matching(fuzzy);
+        self
+    }
+
+    pub fn catch_panics(mut self, catch_panics: bool) -> Self {
+        self.window.set_catch_panics(catch_panics);
+        self
+    }
+
+    pub fn color_panel(mut self, color_panel: bool) -> Self {
+        self.window.set_color_panel(color_panel);
+        self
+    }
+
+    pub fn slider_panel(mut self, sliders: bool) -> Self {
+        self.window.set_slider_panel(sliders);
+        self
+    }
+
+    pub fn overview(mut self, overview: bool) -> Self {
+        self.window.set_overview(overview);
+        self
+    }
+
+    pub fn slow_command_warning(mut self, threshold: Duration) -> Self {
+        self.window.set_slow_command_warning(Some(threshold));
+        self
+    }
+
+    pub fn about_text(mut self, text: &str) -> Self {
+        self.window.set_about_text(text);
+        self
+    }
+
+    pub fn guard_command(mut self, name: &str) -> Self {
+        self.window.guard_command(name);
+        self
+    }
+
+    pub fn build(self) -> ConsoleWindow {
+        self.window
+    }
+}
+
+impl Default for ConsoleWindowBuilder {
+    fn default() -> Self {
+        ConsoleWindowBuilder::new()
+    }
+}
+
+impl From<ConsoleWindowBuilder> for ConsoleWindow {
+    fn from(builder: ConsoleWindowBuilder) -> ConsoleWindow {
+        builder.build()
+    }
+}
@@ -0,0 +1,907 @@
+use crate::{
+    dispatch, format_elapsed, line_ranges, quote_arg, resolve_command, resolve_path, span_json,
+    spans_text, split_commands, split_redirect, tokenize, ChangeHook, ColoredConsole, ConsoleError,
+    ConsoleResult, ConsoleSink, Context, IConsoleExt, IVisitExt, Session, SpanRole, TextSpan,
+    ValueFormat, VisitMutExt, WindowRequest, DIM_COLOR, MAX_ALIAS_DEPTH,
+};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Color of echoed commands, dimmer than output so the two are easy to tell apart.
+const INPUT_COLOR: [f32; 4] = [0.7, 0.75, 0.85, 1.];
+
+/// How long a guarded command waits to be run a second time, confirming it.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How many scripts deep `source` can go, so a script which sources itself still ends.
+const MAX_SCRIPT_DEPTH: usize = 16;
+
+/// Color of the prompt in front of a command that failed.
+const FAILED_COLOR: [f32; 4] = [1., 0.3, 0.3, 1.];
+
+pub(crate) type CommandHandler = Box<dyn FnMut(&[&str], &mut dyn IConsoleExt) + Send + Sync>;
+
+/// A command added with `Console::add_command`.
+pub(crate) struct Command {
+    name: String,
+    help: String,
+    handler: CommandHandler,
+}
+
+/// Offer each of `commands` to `f` as an action.
+pub(crate) fn visit_commands(
+    commands: &mut [Command],
+    f: &mut dyn FnMut(&mut dyn cvar::INode),
+    console: &mut dyn IConsoleExt,
+) {
+    for cmd in commands {
+        let handler = &mut cmd.handler;
+        f(&mut cvar::Action(&cmd.name, &cmd.help, |args, _| {
+            handler(args, console)
+        }));
+    }
+}
+
+/// A property shown in the watch panel, and the last value seen for it.
+// only the window reads the values, when it draws them
+#[cfg_attr(not(feature = "window"), allow(dead_code))]
+pub(crate) struct Watch {
+    pub(crate) path: String,
+    pub(crate) value: Option<String>,
+    pub(crate) changed: Option<Instant>,
+}
+
+/// The part of a console which runs commands and keeps their output, without drawing anything.
+///
+/// `ConsoleWindow` draws one of these with imgui, and `HeadlessConsole` runs one without a window.
+/// Everything here works without the `window` feature, so it needs no imgui. It holds the output,
+/// history, aliases, watches, scripts and command settings, which the window reaches through
+/// `Deref`, so every setter here can be called on a `ConsoleWindow` too.
+pub struct Console {
+    pub(crate) output: ColoredConsole,
+    /// Drawn before each echoed command, set with `set_prompt`.
+    pub(crate) prompt_label: String,
+    pub(crate) prompt_color: [f32; 4],
+    /// Shown instead of the prompt label in front of echoed commands, if set.
+    pub(crate) echo_prefix: Option<String>,
+    pub(crate) input_color: [f32; 4],
+    pub(crate) history: Vec<String>,
+    pub(crate) history_limit: usize,
+    /// Whether `reset_state` empties the history as well.
+    pub(crate) reset_clears_history: bool,
+    /// Set by `reset_state`, until `take_state_reset`.
+    pub(crate) state_reset: bool,
+    /// How many times `reset_state` has run, so a frontend can reset its own state along with it.
+    pub(crate) resets: usize,
+    /// Lines dropped from the front of the output so far, by `clear` or the scrollback limit, so
+    /// a frontend can keep what it stores for each line in step.
+    pub(crate) dropped_lines: usize,
+    /// Spans dropped from the front of the output so far, counted like `dropped_lines`.
+    pub(crate) dropped_spans: usize,
+    pub(crate) value_format: ValueFormat,
+    pub(crate) ignore_case: bool,
+    pub(crate) help_page_lines: usize,
+    pub(crate) catch_panics: bool,
+    pub(crate) fuzzy: bool,
+    pub(crate) about_text: Option<String>,
+    /// Commands taking longer than this are reported with their time.
+    pub(crate) slow_command: Option<Duration>,
+    pub(crate) commands: Vec<Command>,
+    /// Commands to run, with the id of the `source -e` script each came from, and how many
+    /// scripts deep it is.
+    pub(crate) queue: VecDeque<(String, Option<usize>, usize)>,
+    /// Script of the queued command being run, which the rest of its line stays part of.
+    pub(crate) current_script: Option<usize>,
+    /// How many scripts deep the queued command being run is.
+    pub(crate) script_depth: usize,
+    pub(crate) scripts_started: usize,
+    pub(crate) started: Instant,
+    pub(crate) commands_run: usize,
+    pub(crate) watches: Vec<Watch>,
+    /// There is a watch panel to show `watches` in. Not over a remote connection.
+    pub(crate) shows_watches: bool,
+    pub(crate) scrollback_limit: usize,
+    pub(crate) aliases: HashMap<String, String>,
+    /// Lines run since the last `take_executed`, and whether they succeeded. None if not recording.
+    pub(crate) executed: Option<Vec<(String, bool)>>,
+    /// Properties changed since the last `take_changes`, with their new values. None if not
+    /// recording.
+    pub(crate) changes: Option<Vec<(String, String)>>,
+    pub(crate) change_hooks: Vec<ChangeHook>,
+    pub(crate) sink: ConsoleSink,
+    pub(crate) log_sink: Option<ConsoleSink>,
+    /// Frames left before queued commands run again, set by `wait`.
+    pub(crate) wait_frames: usize,
+    pub(crate) script_dir: Option<PathBuf>,
+    /// Commands which have to be confirmed before they run, besides a full `reset`.
+    pub(crate) guarded: Vec<String>,
+    /// The guarded command waiting to be run again, and when it was first tried.
+    pub(crate) confirm: Option<(String, Instant)>,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Console {
+            output: ColoredConsole {
+                buf: VecDeque::new(),
+                error: None,
+                hook: None,
+            },
+            prompt_label: " > ".to_string(),
+            prompt_color: [0., 1., 1., 1.],
+            echo_prefix: None,
+            input_color: INPUT_COLOR,
+            history: vec![],
+            history_limit: 1000,
+            reset_clears_history: true,
+            state_reset: false,
+            resets: 0,
+            dropped_lines: 0,
+            dropped_spans: 0,
+            value_format: ValueFormat::default(),
+            ignore_case: false,
+            help_page_lines: 30,
+            catch_panics: false,
+            fuzzy: false,
+            about_text: None,
+            slow_command: None,
+            commands: vec![],
+            queue: VecDeque::new(),
+            current_script: None,
+            script_depth: 0,
+            scripts_started: 0,
+            started: Instant::now(),
+            commands_run: 0,
+            watches: vec![],
+            shows_watches: true,
+            scrollback_limit: 5000,
+            aliases: HashMap::new(),
+            executed: None,
+            changes: None,
+            change_hooks: vec![],
+            sink: ConsoleSink::default(),
+            log_sink: None,
+            wait_frames: 0,
+            script_dir: None,
+            guarded: vec![],
+            confirm: None,
+        }
+    }
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Console::new()
+    }
+}
+
+impl Console {
+    pub fn clear(&mut self) {
+        self.dropped_lines += line_ranges(&self.output.buf).len();
+        self.dropped_spans += self.output.buf.len();
+        self.output.buf.clear();
+    }
+
+    /// Everything in the scrollback as JSON, for attaching to bug reports or comparing in tests.
+    ///
+    /// An array with an object for each span, holding its `role` (`prompt`, `input`, `output` or
+    /// `error`), `text`, `color` and the `time` it was written, in seconds since the console was
+    /// created. Collapsed output also has its hidden spans under `detail`.
+    pub fn export_json(&self) -> String {
+        let spans = self
+            .output
+            .buf
+            .iter()
+            .map(|span| span_json(span, self.started))
+            .collect::<Vec<_>>();
+        format!("[\n{}\n]\n", spans.join(",\n"))
+    }
+
+    pub fn write<S>(&mut self, text: S)
+    where
+        S: Into<TextSpan>,
+    {
+        self.output.write(text);
+    }
+
+    pub fn writeln<S>(&mut self, text: S)
+    where
+        S: Into<TextSpan>,
+    {
+        self.output.writeln(text);
+    }
+
+    /// Write text with inline colors such as `"[#ff0000]error[/] ok"`.
+    pub fn write_markup(&mut self, text: &str) {
+        self.output.write_markup(text);
+    }
+
+    pub fn write_colored(&mut self, c: [f32; 4], t: &str) {
+        self.output.write_colored(c, t);
+    }
+
+    pub fn draw_prompt(&mut self) {
+        self.write(TextSpan {
+            text: self.prompt_label.clone(),
+            color: self.prompt_color,
+            role: SpanRole::Prompt,
+            ..Default::default()
+        });
+    }
+
+    /// Call `hook` with every span written to the console, to mirror the output somewhere else.
+    ///
+    /// The hook runs on whichever thread draws the console, in the middle of a frame, so it must
+    /// not block. Hand the text off to another thread if it needs to do anything slow.
+    pub fn set_output_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&TextSpan) + Send + Sync + 'static,
+    {
+        self.output.hook = Some(Box::new(hook));
+    }
+
+    /// Add a command without needing a config to put it on.
+    ///
+    /// It is listed in help and completed like any other. `help` follows the same format as a
+    /// `cvar::Action` description, so put `<args>` on its first line followed by a newline.
+    /// Mark optional ones as `[args]`, or the command is refused when they are left out.
+    pub fn add_command<F>(&mut self, name: &str, help: &str, handler: F)
+    where
+        F: FnMut(&[&str], &mut dyn IConsoleExt) + Send + Sync + 'static,
+    {
+        self.commands.push(Command {
+            name: name.to_string(),
+            help: help.to_string(),
+            handler: Box::new(handler),
+        });
+    }
+
+    /// Ask before running `name`, which has to be repeated within a few seconds, or given `--yes`.
+    ///
+    /// `reset` with no arguments is always guarded like this. Scripts need the `--yes` too.
+    pub fn guard_command(&mut self, name: &str) {
+        self.guarded.push(name.to_string());
+    }
+
+    /// The command to run for `segment`, or None if it is guarded and still has to be confirmed.
+    ///
+    /// Commands are recognised by the name they run as, so abbreviations and commands run
+    /// through `time` are guarded too. A `--yes` argument confirms it straight away, and is
+    /// removed before it runs.
+    fn confirmed<V: cvar::IVisit>(&mut self, root: &mut V, segment: &str) -> Option<String> {
+        let words = tokenize(segment);
+        let ignore_case = self.ignore_case;
+        let mut resolve = |word: &str| {
+            resolve_command(root, word, ignore_case)
+                .unwrap_or_else(|| word.to_string())
+                .to_lowercase()
+        };
+        // `time` runs the command after it, so that is the one to check
+        let mut start = 0;
+        while start + 1 < words.len() && resolve(&words[start]) == "time" {
+            start += 1;
+        }
+        let name = words.get(start).map_or(String::new(), |w| resolve(w));
+        let guarded = if name == "reset" {
+            words[start + 1..].iter().all(|w| w == "--yes")
+        } else {
+            self.guarded.iter().any(|g| g.to_lowercase() == name)
+        };
+        if !guarded {
+            return Some(segment.to_string());
+        }
+        if words.iter().any(|w| w == "--yes") {
+            let words = words.iter().filter(|w| *w != "--yes");
+            return Some(words.map(|w| quote_arg(w)).collect::<Vec<_>>().join(" "));
+        }
+
+        let line = words.join(" ");
+        let now = Instant::now();
+        let confirmed = match self.confirm.take() {
+            Some((last, at)) => last == line && now - at < CONFIRM_TIMEOUT,
+            None => false,
+        };
+        if confirmed {
+            return Some(segment.to_string());
+        }
+        self.confirm = Some((line, now));
+        self.write(TextSpan {
+            text: format!(
+                "'{}' can't be undone. Run it again within {} seconds, or add --yes, to go ahead\n",
+                segment.trim(),
+                CONFIRM_TIMEOUT.as_secs()
+            ),
+            color: [1., 1., 0., 1.],
+            ..Default::default()
+        });
+        None
+    }
+
+    /// How many lines `help` shows before splitting the rest into pages, reached with `help 2` and
+    /// so on. 0 shows everything at once. The default is 30.
+    pub fn set_help_page_lines(&mut self, lines: usize) {
+        self.help_page_lines = lines;
+    }
+
+    /// Match the names of your own properties and commands regardless of case, as well as the
+    /// builtins. Off by default.
+    pub fn set_case_insensitive(&mut self, ignore_case: bool) {
+        self.ignore_case = ignore_case;
+    }
+
+    /// Let Tab completion and `find` match names the typed letters appear in, in order, so `pvel`
+    /// finds `paddle.velocity`. The best matches are listed first, ranked by `fuzzy_score`. Off by
+    /// default, which only completes names starting with the word and only finds names
+    /// containing each term.
+    pub fn set_fuzzy_matching(&mut self, fuzzy: bool) {
+        self.fuzzy = fuzzy;
+    }
+
+    /// Report a command that panics as an error, instead of taking the whole game down with it.
+    /// Off by default.
+    ///
+    /// The panic message is still printed by the panic hook. Whatever the command was in the
+    /// middle of changing is left as it was, so the config may be in an odd state afterwards.
+    /// Has no effect when building with `panic = "abort"`.
+    pub fn set_catch_panics(&mut self, catch_panics: bool) {
+        self.catch_panics = catch_panics;
+    }
+
+    /// Show how long any command took when it takes longer than `threshold`, as `time` does.
+    ///
+    /// Useful for spotting commands that stutter the game. Off by default.
+    pub fn set_slow_command_warning(&mut self, threshold: Option<Duration>) {
+        self.slow_command = threshold;
+    }
+
+    /// Extra text for `version` to print, such as your game's name and build number.
+    pub fn set_about_text(&mut self, text: &str) {
+        self.about_text = Some(text.to_string());
+    }
+
+    /// Change the prompt shown before each command that is run. The default is a cyan `" > "`.
+    pub fn set_prompt(&mut self, label: &str, color: [f32; 4]) {
+        self.prompt_label = label.to_string();
+        self.prompt_color = color;
+    }
+
+    /// Change how commands are echoed into the output. The default is the prompt label, followed
+    /// by the command in a dim blue-gray.
+    ///
+    /// `prefix` replaces the prompt label in front of echoed commands, so the scrollback can use
+    /// something different, such as `"$ "`. Commands already in the scrollback keep their look.
+    pub fn set_echo_style(&mut self, prefix: Option<&str>, color: [f32; 4]) {
+        self.echo_prefix = prefix.map(|p| p.to_string());
+        self.input_color = color;
+    }
+
+    /// Cut property values longer than `width` characters short in help and get output.
+    ///
+    /// `info <var>` still shows the full value. 0 shows everything, which is the default.
+    pub fn set_value_display_width(&mut self, width: usize) {
+        self.value_format.max_width = if width > 0 { Some(width) } else { None };
+    }
+
+    /// Return the console to a clean slate, as if it had just been created.
+    ///
+    /// This clears the scrollback, the history (see `set_reset_clears_history`), aliases,
+    /// watches, queued commands and any `wait`, and a guarded command waiting to be confirmed. A
+    /// window also empties its prompt and filter, and the amethyst system goes back to the key
+    /// bindings it was created with. Settings such as colors and value width are kept. Unlike the
+    /// `reset` command, no game properties are touched.
+    pub fn reset_state(&mut self) {
+        self.clear();
+        if self.reset_clears_history {
+            self.history.clear();
+        }
+        self.aliases.clear();
+        self.watches.clear();
+        self.queue.clear();
+        self.wait_frames = 0;
+        self.current_script = None;
+        self.script_depth = 0;
+        self.confirm = None;
+        self.state_reset = true;
+        self.resets += 1;
+    }
+
+    /// Whether `reset_state` also empties the command history. On by default.
+    ///
+    /// The history is what `save_history` writes, so the saved history file is emptied too: the
+    /// amethyst system saves it straight away. Turn this off to keep the history and its file.
+    pub fn set_reset_clears_history(&mut self, clear: bool) {
+        self.reset_clears_history = clear;
+    }
+
+    /// Whether `reset_state` has run since the last call, for frontends that keep console state
+    /// of their own, such as key bindings.
+    pub fn take_state_reset(&mut self) -> bool {
+        std::mem::replace(&mut self.state_reset, false)
+    }
+
+    /// Show numbers with thousands separators (`1,000,000`) in help, get output and the watch
+    /// panel.
+    ///
+    /// This only affects what is displayed. Values are stored, and accepted by `set`, without them.
+    pub fn set_number_grouping(&mut self, group: bool) {
+        self.value_format.group_numbers = group;
+    }
+
+    /// Show `path` in the watch panel at the top of the window, updated every frame.
+    pub fn watch(&mut self, path: &str) {
+        if !self.watches.iter().any(|w| w.path == path) {
+            self.watches.push(Watch {
+                path: path.to_string(),
+                value: None,
+                changed: None,
+            });
+        }
+    }
+
+    pub fn unwatch(&mut self, path: &str) {
+        self.watches.retain(|w| w.path != path);
+    }
+
+    /// Directory `source`, `>`, `save` and `load` look in for files given as relative paths.
+    ///
+    /// They are found relative to the working directory if this isn't set.
+    pub fn set_script_dir<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.script_dir = Some(dir.into());
+    }
+
+    /// Where `file` is, relative to the script directory if there is one.
+    fn script_path(&self, file: &str) -> PathBuf {
+        resolve_path(self.script_dir.as_deref(), file)
+    }
+
+    /// The commands in a script, skipping blank lines and `#` comments.
+    fn read_script(&self, file: &str) -> Result<Vec<String>, ConsoleError> {
+        let path = self.script_path(file);
+        let text = std::fs::read_to_string(&path).map_err(|e| {
+            ConsoleError::Custom(format!("Could not read {}: {}", path.display(), e).into())
+        })?;
+        Ok(text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string())
+            .collect())
+    }
+
+    /// Write the output of a command redirected with `>` or `>>`.
+    fn write_redirect(&self, file: &str, append: bool, text: &str) -> Result<(), ConsoleError> {
+        use std::io::Write;
+        let path = self.script_path(file);
+        std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&path)
+            .and_then(|mut f| f.write_all(text.as_bytes()))
+            .map_err(|e| {
+                ConsoleError::Custom(format!("Could not write {}: {}", path.display(), e).into())
+            })
+    }
+
+    /// Keep a list of every command line run, for `take_executed`. Off by default.
+    pub fn set_record_executed(&mut self, record: bool) {
+        self.executed = if record { Some(vec![]) } else { None };
+    }
+
+    /// Every command line run since the last call, and whether it succeeded.
+    ///
+    /// Always empty unless enabled with `set_record_executed`.
+    pub fn take_executed(&mut self) -> Vec<(String, bool)> {
+        match &mut self.executed {
+            Some(executed) => std::mem::replace(executed, vec![]),
+            None => vec![],
+        }
+    }
+
+    /// Call `hook` with the path and new value of every property a command changes.
+    ///
+    /// Runs after each command that changed something, whether by typing a value, `set`, `reset`,
+    /// `load`, or the slider and color panels. Your own actions writing to their fields directly
+    /// aren't seen.
+    pub fn on_change<F>(&mut self, hook: F)
+    where
+        F: FnMut(&str, &str) + Send + Sync + 'static,
+    {
+        self.change_hooks.push(Box::new(hook));
+    }
+
+    /// Keep a list of every property changed by a command, for `take_changes`. Off by default.
+    pub fn set_record_changes(&mut self, record: bool) {
+        self.changes = if record { Some(vec![]) } else { None };
+    }
+
+    /// Every property changed since the last call, with its new value.
+    ///
+    /// Always empty unless enabled with `set_record_changes`.
+    pub fn take_changes(&mut self) -> Vec<(String, String)> {
+        match &mut self.changes {
+            Some(changes) => std::mem::replace(changes, vec![]),
+            None => vec![],
+        }
+    }
+
+    /// Pass changed properties on to the `on_change` hooks, and to `take_changes` if recording.
+    pub(crate) fn record_changes(&mut self, changes: Vec<(String, String)>) {
+        for (path, value) in &changes {
+            for hook in &mut self.change_hooks {
+                hook(path, value);
+            }
+        }
+        if let Some(record) = &mut self.changes {
+            record.extend(changes);
+        }
+    }
+
+    /// Make `name` run `cmd`, which may be several commands separated by `;`.
+    ///
+    /// Anything typed after the alias is added to the end of `cmd`.
+    pub fn alias(&mut self, name: &str, cmd: &str) {
+        self.aliases.insert(name.to_string(), cmd.to_string());
+    }
+
+    /// Remove an alias, returning false if there was none.
+    pub fn unalias(&mut self, name: &str) -> bool {
+        self.aliases.remove(name).is_some()
+    }
+
+    /// What `cmd` runs instead, if it starts with an alias.
+    fn expand_alias(&self, cmd: &str) -> Option<String> {
+        let cmd = cmd.trim_start();
+        let (name, rest) = match cmd.find(char::is_whitespace) {
+            Some(i) => cmd.split_at(i),
+            None => (cmd, ""),
+        };
+        self.aliases
+            .get(name)
+            .map(|expanded| format!("{}{}", expanded, rest))
+    }
+
+    fn list_aliases(&mut self) {
+        let mut aliases = self.aliases.iter().collect::<Vec<_>>();
+        aliases.sort();
+        let out = if aliases.is_empty() {
+            "No aliases".to_string()
+        } else {
+            aliases
+                .iter()
+                .map(|(name, cmd)| format!("{} = {}", name, cmd))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        self.output.write_result(out.into());
+    }
+
+    /// Keep at most `limit` spans of output, dropping the oldest. Defaults to 5000.
+    pub fn set_scrollback_limit(&mut self, limit: usize) {
+        self.scrollback_limit = limit;
+        self.trim_scrollback();
+    }
+
+    fn trim_scrollback(&mut self) {
+        let buf = &mut self.output.buf;
+        if buf.len() > self.scrollback_limit {
+            let extra = buf.len() - self.scrollback_limit;
+            self.dropped_lines += line_ranges(buf.iter().take(extra)).len();
+            self.dropped_spans += extra;
+            buf.drain(..extra);
+        }
+    }
+
+    /// Keep at most `limit` commands in the history, dropping the oldest. Defaults to 1000.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        self.trim_history();
+    }
+
+    /// Read commands saved by `save_history`, one per line, in front of the current history.
+    pub fn load_history(&mut self, path: &Path) -> io::Result<()> {
+        let text = std::fs::read_to_string(path)?;
+        let mut history = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_string())
+            .collect::<Vec<_>>();
+        history.append(&mut self.history);
+        self.history = history;
+        self.trim_history();
+        Ok(())
+    }
+
+    /// Write the command history to `path`, one command per line.
+    pub fn save_history(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        for cmd in &self.history {
+            text.push_str(cmd);
+            text.push('\n');
+        }
+        std::fs::write(path, text)
+    }
+
+    /// Add `cmd` to the history, as if it had been typed in. Blank commands are skipped.
+    pub fn push_history(&mut self, cmd: &str) {
+        if !cmd.trim().is_empty() {
+            self.history.push(cmd.to_string());
+        }
+        self.trim_history();
+    }
+
+    fn trim_history(&mut self) {
+        if self.history.len() > self.history_limit {
+            let extra = self.history.len() - self.history_limit;
+            self.history.drain(..extra);
+        }
+    }
+
+    /// Run `cmd` on the next frame instead of right away.
+    ///
+    /// Queued commands run in order at the start of `build` (or `run_queued`), so this is safe to
+    /// call from anywhere, including while a command is executing.
+    pub fn queue_command(&mut self, cmd: String) {
+        self.queue.push_back((cmd, None, 0));
+    }
+
+    /// Run everything queued by `queue_command`, and show output written through sinks. `build`
+    /// calls this for you.
+    ///
+    /// Commands queued while draining are left for the next call.
+    pub fn run_queued(&mut self, root: &mut dyn IVisitExt) {
+        self.drain_sinks();
+        if self.wait_frames > 0 {
+            self.wait_frames -= 1;
+        } else {
+            self.run_queue(root);
+        }
+        // also catches anything written by the game between frames
+        self.trim_scrollback();
+    }
+
+    fn run_queue(&mut self, root: &mut dyn IVisitExt) {
+        for _ in 0..self.queue.len() {
+            if self.wait_frames > 0 {
+                break;
+            }
+            if let Some((cmd, script, depth)) = self.queue.pop_front() {
+                self.echo_cmd(&cmd);
+                self.current_script = script;
+                self.script_depth = depth;
+                let failed = self.execute(root, &cmd).is_err();
+                self.current_script = None;
+                self.script_depth = 0;
+                if failed {
+                    self.mark_failed();
+                }
+                if let (true, Some(id)) = (failed, script) {
+                    self.stop_script(id);
+                }
+            }
+        }
+    }
+
+    /// Drop what is left of a `source -e` script after one of its commands failed.
+    fn stop_script(&mut self, id: usize) {
+        let before = self.queue.len();
+        self.queue.retain(|(_, script, _)| *script != Some(id));
+        let skipped = before - self.queue.len();
+        self.write(TextSpan {
+            text: format!("Script stopped, skipping {} commands\n", skipped),
+            color: [1., 1., 0., 1.],
+            ..Default::default()
+        });
+    }
+
+    /// Show the prompt of the last command run in red, as it failed.
+    fn mark_failed(&mut self) {
+        let prompt = self
+            .output
+            .buf
+            .iter_mut()
+            .rev()
+            .find(|span| span.role == SpanRole::Prompt);
+        if let Some(span) = prompt {
+            span.color = FAILED_COLOR;
+        }
+    }
+
+    /// Show a command in the output as if it had been typed in.
+    pub(crate) fn echo_cmd(&mut self, cmd: &str) {
+        let prefix = self.echo_prefix.as_ref().unwrap_or(&self.prompt_label);
+        self.write(TextSpan {
+            text: prefix.clone(),
+            color: self.prompt_color,
+            role: SpanRole::Prompt,
+            ..Default::default()
+        });
+        self.write(TextSpan {
+            text: format!("{}\n", cmd),
+            color: self.input_color,
+            role: SpanRole::Input,
+            ..Default::default()
+        });
+    }
+
+    pub fn run_cmd(&mut self, root: &mut dyn IVisitExt, cmd: String) {
+        if self.execute(root, &cmd).is_err() {
+            self.mark_failed();
+        }
+    }
+
+    /// Run a command line and return its result, as well as writing it to the console.
+    ///
+    /// When the line holds several commands this is the first error, or the result of the last
+    /// command if they all succeeded.
+    pub fn execute(&mut self, root: &mut dyn IVisitExt, line: &str) -> ConsoleResult {
+        self.commands_run += 1;
+        let context = Context {
+            format: self.value_format,
+            session: Some(Session {
+                started: self.started,
+                commands: self.commands_run,
+            }),
+            ignore_case: self.ignore_case,
+            help_page_lines: self.help_page_lines,
+            catch_panics: self.catch_panics,
+            fuzzy: self.fuzzy,
+            #[cfg(feature = "persist")]
+            script_dir: self.script_dir.clone(),
+        };
+        // Taken while running, so commands added with `add_command` are found like any other
+        let mut commands = std::mem::replace(&mut self.commands, vec![]);
+        let mut root = VisitMutExt(|f, console| {
+            root.visit_mut_ext(f, console);
+            visit_commands(&mut commands, f, console);
+        });
+        let mut result = ConsoleResult(Ok(String::new()));
+        let mut pending = split_commands(line)
+            .into_iter()
+            .map(|cmd| (cmd.to_string(), 0))
+            .collect::<VecDeque<_>>();
+        while let Some((segment, depth)) = pending.pop_front() {
+            if let Some(expanded) = self.expand_alias(&segment) {
+                if depth < MAX_ALIAS_DEPTH {
+                    for cmd in split_commands(&expanded).into_iter().rev() {
+                        pending.push_front((cmd.to_string(), depth + 1));
+                    }
+                    continue;
+                }
+                let e =
+                    ConsoleError::InvalidValue(format!("aliases nested too deeply in {}", line));
+                self.output.write_result(e.clone().into());
+                if result.is_ok() {
+                    result = e.into();
+                }
+                continue;
+            }
+
+            let (segment, redirect) = match split_redirect(&segment) {
+                Some((cmd, file, append)) => (cmd.to_string(), Some((file, append))),
+                None => (segment, None),
+            };
+            let segment = match self.confirmed(&mut root, &segment) {
+                Some(segment) => segment,
+                None => continue,
+            };
+            let start = Instant::now();
+            let mut out = dispatch(&mut root, &segment, context.clone());
+            let elapsed = start.elapsed();
+            if self.slow_command.map_or(false, |limit| elapsed > limit) {
+                let msg = format!("{} took {}\n", segment.trim(), format_elapsed(elapsed));
+                out.console.write_colored(DIM_COLOR, &msg);
+            }
+            self.record_changes(std::mem::replace(&mut out.changes, vec![]));
+            let mut script = vec![];
+            let mut script_id = self.current_script;
+            for request in out.requests {
+                match request {
+                    WindowRequest::Clear => self.clear(),
+                    WindowRequest::ResetState => self.reset_state(),
+                    WindowRequest::Watch(_) | WindowRequest::Unwatch(_) if !self.shows_watches => {
+                        let msg = "watches are only shown in the console window".to_string();
+                        out.result = ConsoleError::InvalidUsage(msg).into();
+                    }
+                    WindowRequest::Watch(path) => self.watch(&path),
+                    WindowRequest::Unwatch(path) => self.unwatch(&path),
+                    WindowRequest::Alias(name, cmd) => self.alias(&name, &cmd),
+                    WindowRequest::Unalias(name) => {
+                        if !self.unalias(&name) {
+                            let e = ConsoleError::InvalidValue(format!("no alias named {}", name));
+                            out.result = e.into();
+                        }
+                    }
+                    WindowRequest::ListAliases => self.list_aliases(),
+                    WindowRequest::About => {
+                        if let Some(about) = &self.about_text {
+                            out.console.writeln(about.clone());
+                        }
+                    }
+                    WindowRequest::Wait(frames) => self.wait_frames = frames,
+                    WindowRequest::Source(file, _) if self.script_depth >= MAX_SCRIPT_DEPTH => {
+                        let e = ConsoleError::InvalidValue(format!(
+                            "scripts nested too deeply in {}",
+                            file
+                        ));
+                        out.result = e.into();
+                    }
+                    WindowRequest::Source(file, stop_on_error) => match self.read_script(&file) {
+                        Ok(lines) => {
+                            script = lines;
+                            if stop_on_error {
+                                self.scripts_started += 1;
+                                script_id = Some(self.scripts_started);
+                            }
+                        }
+                        Err(e) => out.result = e.into(),
+                    },
+                }
+            }
+            if let Some((file, append)) = redirect {
+                // errors are still shown, only the output goes to the file
+                let mut text = String::new();
+                if let Ok(val) = &out.result.0 {
+                    text.push_str(val);
+                    if !val.is_empty() && !val.ends_with('\n') {
+                        text.push('\n');
+                    }
+                }
+                let (errors, written): (VecDeque<_>, VecDeque<_>) = out
+                    .console
+                    .buf
+                    .drain(..)
+                    .partition(|span| span.role == SpanRole::Error);
+                text.push_str(&spans_text(&written));
+                out.console.buf = errors;
+                out.result = if file.is_empty() {
+                    ConsoleError::InvalidUsage("<command> > <file>".to_string()).into()
+                } else {
+                    match self.write_redirect(&file, append, &text) {
+                        Ok(()) => "".into(),
+                        Err(e) => e.into(),
+                    }
+                };
+            }
+            // only what is shown is formatted, files and callers get the value as it is
+            let shown = match &out.result.0 {
+                Ok(val) if out.get => context.format.apply(val).into(),
+                _ => out.result.clone(),
+            };
+            self.output.write_result(shown);
+            for span in out.console.buf.drain(..) {
+                self.output.write(span);
+            }
+            // Actions can't return an error, only write one, so that counts as failing too
+            let status = match out.console.error.take() {
+                Some(msg) if out.result.is_ok() => ConsoleError::Custom(msg.into()).into(),
+                _ => out.result,
+            };
+            if result.is_ok() {
+                result = status;
+            }
+
+            if self.wait_frames > 0 || !script.is_empty() {
+                // the rest of the line runs from the queue, after the wait or the script
+                for (cmd, _) in pending.into_iter().rev() {
+                    self.queue
+                        .push_front((cmd, self.current_script, self.script_depth));
+                }
+                for cmd in script.into_iter().rev() {
+                    self.queue
+                        .push_front((cmd, script_id, self.script_depth + 1));
+                }
+                break;
+            }
+        }
+        self.commands = commands;
+        self.trim_scrollback();
+        if let Some(executed) = &mut self.executed {
+            executed.push((line.to_string(), result.is_ok()));
+        }
+        result
+    }
+}
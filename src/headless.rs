@@ -0,0 +1,102 @@
+#[cfg(feature = "window")]
+use crate::ConsoleWindow;
+use crate::{spans_text, Console, ConsoleResult, IVisitExt, TextSpan};
+use std::io::{self, BufRead, Write};
+
+/// Console without a window, for server builds, tools, and tests.
+///
+/// Commands run exactly as they do in a `ConsoleWindow`, aliases, scripts and `wait` included,
+/// but the output is handed back instead of drawn. Only the `Console` core is used, so this works
+/// with the `window` feature turned off, which leaves imgui out of the build.
+///
+/// ```ignore
+/// let mut console = HeadlessConsole::new();
+/// let stdin = std::io::stdin();
+/// console.run_stdio(&mut config, stdin.lock(), std::io::stdout())?;
+/// ```
+pub struct HeadlessConsole {
+    console: Console,
+}
+
+impl HeadlessConsole {
+    pub fn new() -> HeadlessConsole {
+        HeadlessConsole::from_console(Console::new())
+    }
+
+    /// Run commands with the settings of `console`.
+    pub fn from_console(console: Console) -> HeadlessConsole {
+        HeadlessConsole { console }
+    }
+
+    /// Run commands with the settings of `window`, such as one made with `ConsoleWindow::builder`.
+    #[cfg(feature = "window")]
+    pub fn from_window(window: ConsoleWindow) -> HeadlessConsole {
+        HeadlessConsole::from_console(window.into_console())
+    }
+
+    /// The console behind this one, for aliases, sinks, and everything else it can be set up with.
+    pub fn console(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    /// Run a command line, then anything it queued, such as the lines of a `source`d script.
+    ///
+    /// The output is kept until `take_output` or `take_text`.
+    pub fn execute(&mut self, root: &mut dyn IVisitExt, line: &str) -> ConsoleResult {
+        let result = self.console.execute(root, line);
+        self.run_pending(root);
+        result
+    }
+
+    /// Run every queued command, counting each pass as a frame for `wait`.
+    ///
+    /// Scripts can only be nested so deep, so this ends even for a script which sources itself.
+    pub fn run_pending(&mut self, root: &mut dyn IVisitExt) {
+        loop {
+            // always once, to pick up anything written to a sink
+            self.console.run_queued(root);
+            if self.console.queue.is_empty() {
+                break;
+            }
+        }
+    }
+
+    /// Everything written since the last call.
+    pub fn take_output(&mut self) -> Vec<TextSpan> {
+        self.console.output.buf.drain(..).collect()
+    }
+
+    /// Like `take_output`, but as plain text.
+    pub fn take_text(&mut self) -> String {
+        spans_text(&self.take_output())
+    }
+
+    /// Run each line of `input` as a command, writing its output to `output`, until `input` ends.
+    pub fn run_stdio<R, W>(
+        &mut self,
+        root: &mut dyn IVisitExt,
+        input: R,
+        mut output: W,
+    ) -> io::Result<()>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            self.execute(root, &line);
+            output.write_all(self.take_text().as_bytes())?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for HeadlessConsole {
+    fn default() -> HeadlessConsole {
+        HeadlessConsole::new()
+    }
+}
@@ -4,7 +4,45 @@ mod amethyst;
 #[cfg(feature = "amethyst-system")]
 pub use crate::amethyst::*;
 
-use imgui::{im_str, ImString};
+#[cfg(feature = "remote")]
+mod remote;
+
+#[cfg(feature = "remote")]
+pub use crate::remote::*;
+
+#[cfg(feature = "testing")]
+mod testing;
+
+#[cfg(feature = "testing")]
+pub use crate::testing::*;
+
+#[cfg(feature = "window")]
+mod builder;
+mod console;
+mod headless;
+mod logger;
+mod sink;
+#[cfg(feature = "window")]
+mod window;
+
+#[cfg(feature = "window")]
+pub use crate::builder::*;
+pub use crate::console::*;
+pub use crate::headless::*;
+pub use crate::logger::*;
+pub use crate::sink::*;
+#[cfg(feature = "window")]
+pub use crate::window::*;
+
+#[cfg(feature = "persist")]
+mod persist;
+
+#[cfg(feature = "persist")]
+pub use crate::persist::*;
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 /// cvar varients. Used to disambiguate which action to perform when unspecified.
 #[derive(Debug)]
@@ -18,7 +56,7 @@ pub enum CmdType {
 /// Error type for ConsoleResult
 ///
 /// Should cover most common use cases, but anything Custom will be displayed as is.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ConsoleError {
     UnknownProperty,
     UnknownCommand,
@@ -33,7 +71,7 @@ pub enum ConsoleError {
 ///
 /// Provides a unified way to handle errors, especially with nested calls.
 /// Use `IConsoleExt.write_result` to display to the user.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ConsoleResult(pub Result<String, ConsoleError>);
 
 impl From<Result<String, ConsoleError>> for ConsoleResult {
@@ -83,42 +121,186 @@ impl std::fmt::Display for ConsoleError {
 
 impl std::error::Error for ConsoleError {}
 
+/// How property values are shown in listings and get output.
+#[derive(Debug, Default, Clone, Copy)]
+struct ValueFormat {
+    /// Values longer than this many characters are cut short.
+    max_width: Option<usize>,
+    /// Separate thousands in numbers with commas.
+    group_numbers: bool,
+}
+
+impl ValueFormat {
+    fn apply(&self, val: &str) -> String {
+        let grouped;
+        let val = match group_digits(val) {
+            Some(g) if self.group_numbers => {
+                grouped = g;
+                &grouped
+            }
+            _ => val,
+        };
+
+        let len = val.chars().count();
+        match self.max_width {
+            Some(width) if len > width => {
+                let short = val.chars().take(width).collect::<String>();
+                format!("{}... ({} chars)", short, len)
+            }
+            _ => val.to_string(),
+        }
+    }
+}
+
+/// How many aliases may expand into other aliases before giving up, in case one refers to itself.
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// Statistics about the window a command was typed into.
+#[derive(Debug, Clone, Copy)]
+struct Session {
+    started: Instant,
+    commands: usize,
+}
+
+/// Window state made available to the builtin commands.
+#[derive(Debug, Default, Clone)]
+struct Context {
+    format: ValueFormat,
+    session: Option<Session>,
+    /// Match every command name regardless of case, not just the builtins.
+    ignore_case: bool,
+    /// Lines on each page of `help`, or 0 to show everything at once.
+    help_page_lines: usize,
+    /// Turn a panicking command into an error rather than unwinding through the caller.
+    catch_panics: bool,
+    /// Let `find` terms match as subsequences, best matches first.
+    fuzzy: bool,
+    /// Directory relative paths given to `save` and `load` are in, as for `source`.
+    #[cfg(feature = "persist")]
+    script_dir: Option<PathBuf>,
+}
+
+/// Where `file` is, relative to `dir` if there is one.
+fn resolve_path(dir: Option<&Path>, file: &str) -> PathBuf {
+    match dir {
+        Some(dir) => dir.join(file),
+        None => PathBuf::from(file),
+    }
+}
+
+/// Insert thousands separators into a plain number such as `-1234567.5`.
+///
+/// Returns None if `val` isn't a number.
+fn group_digits(val: &str) -> Option<String> {
+    let (sign, unsigned) = if val.starts_with('-') {
+        ("-", &val[1..])
+    } else {
+        ("", val)
+    };
+    let (int, frac) = match unsigned.find('.') {
+        Some(i) => unsigned.split_at(i),
+        None => (unsigned, ""),
+    };
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if int.is_empty() || !digits(int) || !digits(frac.trim_start_matches('.')) {
+        return None;
+    }
+
+    let mut out = sign.to_string();
+    for (i, c) in int.chars().enumerate() {
+        if i > 0 && (int.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out.push_str(frac);
+    Some(out)
+}
+
+/// Colors of the parts of a help entry, so long listings are easy to scan.
+const PROP_COLOR: [f32; 4] = [0.4, 0.9, 1., 1.];
+const ACTION_COLOR: [f32; 4] = [0.5, 1., 0.5, 1.];
+const LIST_COLOR: [f32; 4] = [1., 0.8, 0.4, 1.];
+const DIM_COLOR: [f32; 4] = [0.6, 0.6, 0.6, 1.];
+
+fn colored(color: [f32; 4], text: String) -> TextSpan {
+    TextSpan {
+        color,
+        text,
+        ..Default::default()
+    }
+}
+
+/// Make `span` fill the prompt with `command` when clicked.
+fn link(mut span: TextSpan, command: String) -> TextSpan {
+    span.command = Some(command);
+    span
+}
+
+/// The text of `spans` run together, for consoles that can't show color.
+fn spans_text<'a, I>(spans: I) -> String
+where
+    I: IntoIterator<Item = &'a TextSpan>,
+{
+    let mut out = String::new();
+    for span in spans {
+        span_text(span, &mut out);
+    }
+    out
+}
+
 /// Extra features for a node. Provides consistent formatting for help entries.
 trait NodeExt {
-    fn details(&mut self, path: &str, out: &mut String);
+    fn details(&mut self, path: &str, out: &mut Vec<TextSpan>, format: &ValueFormat);
     fn kind(&mut self) -> CmdType;
+    /// Every value a property accepts, if there are only a few. Empty for anything else.
+    fn values(&mut self) -> Vec<String>;
 }
 
 impl<'a> NodeExt for dyn cvar::INode + 'a {
-    fn details(&mut self, path: &str, out: &mut String) {
+    fn details(&mut self, path: &str, out: &mut Vec<TextSpan>, format: &ValueFormat) {
         let desc = self.description().to_string();
         match self.as_node_mut() {
-            cvar::NodeMut::Prop(prop) => out.push_str(&format!(
-                "{}: {} (Default: {})\n\t{}\n",
-                path,
-                prop.get(),
-                prop.default(),
-                desc
-            )),
+            cvar::NodeMut::Prop(prop) => {
+                let (note, desc) = if is_read_only(&desc) {
+                    (
+                        format!(" {}", READ_ONLY),
+                        desc.trim_end_matches(READ_ONLY).trim_end(),
+                    )
+                } else {
+                    (
+                        format!(" (Default: {})", format.apply(&prop.default())),
+                        &desc[..],
+                    )
+                };
+                out.push(link(
+                    colored(PROP_COLOR, path.to_string()),
+                    format!("{} ", path),
+                ));
+                out.push(format!(": {}", format.apply(&prop.get())).into());
+                out.push(colored(DIM_COLOR, note));
+                out.push(format!("\n\t{}\n", desc).into());
+            }
             cvar::NodeMut::Action(_) => {
-                let (args, desc) = {
-                    let mut parts = desc.split('\n');
-                    let part1 = parts.next().unwrap_or("").to_string();
-                    let part2 = parts.collect::<Vec<_>>().join("\n");
-                    if !part2.is_empty() {
-                        (part1, part2)
-                    } else {
-                        ("".to_string(), part1)
-                    }
+                let (args, desc) = match usage_line(&desc) {
+                    Some(args) => (args, &desc[args.len() + 1..]),
+                    None => ("", &desc[..]),
                 };
 
-                out.push_str(path);
+                out.push(link(
+                    colored(ACTION_COLOR, path.to_string()),
+                    format!("{} ", path),
+                ));
                 if !args.is_empty() {
-                    out.push_str(&format!(" {}", args));
+                    out.push(colored(DIM_COLOR, format!(" {}", args)));
                 }
-                out.push_str(&format!(":\n\t{}\n", desc));
+                out.push(format!(":\n\t{}\n", desc).into());
+            }
+            cvar::NodeMut::List(_) => {
+                let name = colored(LIST_COLOR, format!("{}.*", path));
+                out.push(link(name, format!("{}.", path)));
+                out.push(format!(":\n\t{}\n", desc).into());
             }
-            _ => {}
         }
     }
 
@@ -129,6 +311,25 @@ impl<'a> NodeExt for dyn cvar::INode + 'a {
             cvar::NodeMut::Action(_) => CmdType::Action,
         }
     }
+
+    fn values(&mut self) -> Vec<String> {
+        let desc = self.description().to_string();
+        let prop = match self.as_node_mut() {
+            cvar::NodeMut::Prop(prop) => prop,
+            _ => return vec![],
+        };
+        if let Some(line) = desc.lines().find(|l| l.starts_with(VALUES_PREFIX)) {
+            line[VALUES_PREFIX.len()..]
+                .split(", ")
+                .map(|v| v.to_string())
+                .collect()
+        } else if prop.get().parse::<bool>().is_ok() && !is_read_only(&desc) {
+            // cvar can't say what type a property is, but a bool is the only kind shown this way
+            vec!["true".to_string(), "false".to_string()]
+        } else {
+            vec![]
+        }
+    }
 }
 
 /// Handlers for all the basic builtin console commands.
@@ -137,6 +338,8 @@ impl<'a> NodeExt for dyn cvar::INode + 'a {
 pub trait CvarExt {
     fn get(&mut self, var: &str) -> ConsoleResult;
     fn set(&mut self, var: &str, val: &str) -> ConsoleResult;
+    /// Run an action. Fails with `InvalidUsage` if it is given fewer arguments than the
+    /// `<required>` ones in its usage line, without calling it.
     fn call(&mut self, cmd: &str, args: &[&str], console: &mut dyn cvar::IConsole)
         -> ConsoleResult;
     fn reset(&mut self, var: &str) -> ConsoleResult;
@@ -145,6 +348,22 @@ pub trait CvarExt {
     fn help(&mut self, var: &str) -> ConsoleResult;
     fn cmdtype(&mut self, var: &str) -> CmdType;
 
+    /// All paths starting with `prefix`, ignoring case. Returned with their canonical case.
+    fn complete(&mut self, prefix: &str) -> Vec<String>;
+
+    /// All paths `pattern` is a subsequence of, ignoring case, best match first. See `fuzzy_score`.
+    fn complete_fuzzy(&mut self, pattern: &str) -> Vec<String>;
+
+    /// The values a property accepts, if it only accepts a fixed set such as an `EnumProperty` or a
+    /// bool.
+    fn values(&mut self, var: &str) -> Vec<String>;
+
+    /// The path closest to a mistyped `name`, if any is close enough to be what was meant.
+    fn suggest(&mut self, name: &str) -> Option<String>;
+
+    /// The path equal to `name` ignoring case, as long as only one is.
+    fn resolve_case(&mut self, name: &str) -> Option<String>;
+
     /// Turn a textual command into a respective get/set/call action
     fn exec(&mut self, cmd: &str, args: Vec<&str>) -> ConsoleResult;
 }
@@ -167,6 +386,7 @@ impl<T: cvar::IVisit> CvarExt for T {
                     Err(ConsoleError::UnknownProperty)
                 }
             }
+            Err(ref e) if e.is::<ReadOnlyError>() => Err(ConsoleError::InvalidUsage(e.to_string())),
             Err(e) => Err(ConsoleError::InvalidValue(e.to_string())),
         }
         .into()
@@ -178,6 +398,15 @@ impl<T: cvar::IVisit> CvarExt for T {
         args: &[&str],
         console: &mut dyn cvar::IConsole,
     ) -> ConsoleResult {
+        let mut desc = String::new();
+        cvar::console::find(&mut *self, cmd, |node| {
+            desc = node.description().to_string();
+        });
+        if let Some(usage) = usage_line(&desc) {
+            if args.len() < required_args(usage) {
+                return ConsoleError::InvalidUsage(format!("{} {}", cmd, usage)).into();
+            }
+        }
         if cvar::console::invoke(&mut *self, cmd, &args, console) {
             "".into()
         } else {
@@ -187,7 +416,10 @@ impl<T: cvar::IVisit> CvarExt for T {
 
     fn reset(&mut self, var: &str) -> ConsoleResult {
         if cvar::console::reset(&mut *self, var) {
-            "".into()
+            match &*self.get(var) {
+                Ok(val) => format!("{} reset to {}", var, val).into(),
+                Err(_) => "".into(),
+            }
         } else {
             ConsoleError::UnknownProperty.into()
         }
@@ -195,43 +427,102 @@ impl<T: cvar::IVisit> CvarExt for T {
 
     fn reset_all(&mut self) -> ConsoleResult {
         cvar::console::reset_all(&mut *self);
-        "OK".into()
+        let mut count = 0;
+        cvar::console::walk(&mut *self, |_, node| {
+            if let CmdType::Prop = node.kind() {
+                count += 1;
+            }
+        });
+        format!("Reset {} properties", count).into()
     }
 
     fn find(&mut self, filter: &(dyn Fn(&str) -> bool)) -> ConsoleResult {
-        let mut out = String::new();
-        cvar::console::walk(&mut *self, |path, node| {
-            if filter(path) {
-                node.details(path, &mut out);
+        find_with(self, filter, &ValueFormat::default())
+            .map(|spans| spans_text(&spans))
+            .into()
+    }
+
+    fn help(&mut self, var: &str) -> ConsoleResult {
+        help_with(self, var, &ValueFormat::default())
+            .map(|spans| spans_text(&spans))
+            .into()
+    }
+
+    fn cmdtype(&mut self, var: &str) -> CmdType {
+        let mut t = CmdType::NotFound;
+        cvar::console::find(&mut *self, var, |node| {
+            t = node.kind();
+        });
+        t
+    }
+
+    fn complete(&mut self, prefix: &str) -> Vec<String> {
+        let prefix = prefix.to_lowercase();
+        let mut out = vec![];
+        cvar::console::walk(&mut *self, |path, _| {
+            if path.to_lowercase().starts_with(&prefix) {
+                out.push(path.to_string());
             }
         });
+        out.sort();
+        out.dedup();
+        out
+    }
 
-        if !out.is_empty() {
-            out.into()
-        } else {
-            ConsoleError::NoResults.into()
-        }
+    fn complete_fuzzy(&mut self, pattern: &str) -> Vec<String> {
+        let mut out = vec![];
+        cvar::console::walk(&mut *self, |path, _| {
+            if let Some(score) = fuzzy_score(pattern, path) {
+                out.push((score, path.to_string()));
+            }
+        });
+        // ties go in name order, so the same paths always come out the same way
+        out.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        out.dedup_by(|a, b| a.1 == b.1);
+        out.into_iter().map(|(_, path)| path).collect()
     }
 
-    fn help(&mut self, var: &str) -> ConsoleResult {
-        let mut out = String::new();
+    fn values(&mut self, var: &str) -> Vec<String> {
+        let mut values = vec![];
         cvar::console::find(&mut *self, var, |node| {
-            node.details(var, &mut out);
+            values = node.values();
         });
+        values
+    }
 
-        if !out.is_empty() {
-            out.into()
+    fn suggest(&mut self, name: &str) -> Option<String> {
+        let name = name.to_lowercase();
+        // enough for a swapped pair of letters, more for long paths, but very little for short
+        // names where almost anything would be within reach
+        let len = name.chars().count();
+        let max = if len <= 3 {
+            1
         } else {
-            ConsoleError::UnknownProperty.into()
-        }
+            std::cmp::max(2, len / 3)
+        };
+        let mut best: Option<(usize, String)> = None;
+        cvar::console::walk(&mut *self, |path, _| {
+            let distance = edit_distance(&name, &path.to_lowercase());
+            if distance <= max && best.as_ref().map_or(true, |(d, _)| distance < *d) {
+                best = Some((distance, path.to_string()));
+            }
+        });
+        best.map(|(_, path)| path)
     }
 
-    fn cmdtype(&mut self, var: &str) -> CmdType {
-        let mut t = CmdType::NotFound;
-        cvar::console::find(&mut *self, var, |node| {
-            t = node.kind();
+    fn resolve_case(&mut self, name: &str) -> Option<String> {
+        let mut found = vec![];
+        cvar::console::walk(&mut *self, |path, _| {
+            if path.eq_ignore_ascii_case(name) {
+                found.push(path.to_string());
+            }
         });
-        t
+        found.dedup();
+        if found.len() == 1 {
+            found.pop()
+        } else {
+            None
+        }
     }
 
     fn exec(&mut self, cmd: &str, args: Vec<&str>) -> ConsoleResult {
@@ -244,279 +535,1824 @@ impl<T: cvar::IVisit> CvarExt for T {
                 }
             }
             CmdType::Action => {
-                let mut out = String::new();
-                self.call(cmd, &args, &mut out);
+                let mut out = CaptureConsole::default();
+                if let Err(e) = &*self.call(cmd, &args, &mut out) {
+                    return e.clone().into();
+                }
                 out.into()
             }
-            CmdType::List => self.find(&|path: &str| path.starts_with(cmd)),
-            CmdType::NotFound => ConsoleError::UnknownCommand.into(),
+            CmdType::List => {
+                let prefix = format!("{}.", cmd);
+                self.find(&|path: &str| path.starts_with(&prefix))
+            }
+            CmdType::NotFound => {
+                // accept any abbreviation that only one property or command starts with
+                let matches = abbreviations(&mut *self, cmd);
+                match matches.len() {
+                    1 => return self.exec(&matches[0], args),
+                    0 => {}
+                    _ => {
+                        let mut msg = TextSpan::from(ConsoleError::UnknownCommand);
+                        msg.text
+                            .push_str(&format!(". Could be any of: {}", matches.join(", ")));
+                        return ConsoleError::Custom(msg).into();
+                    }
+                }
+                match self.suggest(cmd) {
+                    Some(path) => {
+                        let mut msg = TextSpan::from(ConsoleError::UnknownCommand);
+                        msg.text.push_str(&format!(". Did you mean '{}'?", path));
+                        ConsoleError::Custom(msg).into()
+                    }
+                    None => ConsoleError::UnknownCommand.into(),
+                }
+            }
         }
     }
 }
 
-/// A piece of text with attached metadata such as color
+/// The argument hint on the first line of an action's description, if it has one.
 ///
-/// Construct manually, or convert from a `String` / `ConsoleError` if you want the default color.
-#[derive(Debug)]
-pub struct TextSpan {
-    pub color: [f32; 4],
-    pub text: String,
+/// A description with a single line is all description, with no hint.
+fn usage_line(desc: &str) -> Option<&str> {
+    let mut lines = desc.splitn(2, '\n');
+    let first = lines.next()?;
+    lines.next().filter(|rest| !rest.is_empty()).map(|_| first)
 }
 
-impl<T> From<T> for TextSpan
-where
-    T: Into<String>,
-{
-    fn from(t: T) -> TextSpan {
-        TextSpan {
-            color: [1., 1., 1., 1.],
-            text: t.into(),
+/// How many `<required>` arguments are in a usage hint, not counting any inside `[optional]` ones.
+fn required_args(usage: &str) -> usize {
+    let mut depth = 0;
+    let mut count = 0;
+    for c in usage.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = std::cmp::max(depth, 1) - 1,
+            '<' if depth == 0 => count += 1,
+            _ => {}
         }
     }
+    count
 }
 
-impl From<ConsoleError> for TextSpan {
-    fn from(e: ConsoleError) -> TextSpan {
-        TextSpan {
-            color: [1., 0., 0., 1.],
-            text: e.to_string(),
-        }
+/// Every property or command starting with `cmd`, which it could be an abbreviation of.
+fn abbreviations(root: &mut dyn cvar::IVisit, cmd: &str) -> Vec<String> {
+    let mut matches = vec![];
+    if !cmd.is_empty() {
+        cvar::console::walk(root, |path, node| {
+            let runnable = match node.kind() {
+                CmdType::Prop | CmdType::Action => true,
+                _ => false,
+            };
+            if runnable && path.starts_with(cmd) {
+                matches.push(path.to_string());
+            }
+        });
+        matches.dedup();
     }
+    matches
 }
 
-/// Extended version of cvar::IConsole with support for colored text
-///
-/// See `ColoredConsole` for a concrete implementation
-pub trait IConsoleExt: cvar::IConsole {
-    fn write(&mut self, text: &str);
-    fn write_result(&mut self, result: ConsoleResult);
-    fn write_colored(&mut self, c: [f32; 4], t: &str);
+/// The name `cmd` runs as once its case is fixed and any abbreviation expanded, the same way
+/// `dispatch` and `exec` find it. None if it doesn't name anything.
+fn resolve_command<V: cvar::IVisit>(root: &mut V, cmd: &str, ignore_case: bool) -> Option<String> {
+    let cmd = match root.cmdtype(cmd) {
+        CmdType::NotFound if ignore_case => root.resolve_case(cmd),
+        CmdType::NotFound => VisitMutExt(|_, _| {}).resolve_case(cmd),
+        _ => None,
+    }
+    .unwrap_or_else(|| cmd.to_string());
+    match root.cmdtype(&cmd) {
+        CmdType::NotFound => {
+            let mut matches = abbreviations(root, &cmd);
+            if matches.len() == 1 {
+                matches.pop()
+            } else {
+                None
+            }
+        }
+        _ => Some(cmd),
+    }
 }
 
-impl std::fmt::Display for TextSpan {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.text)
+/// Levenshtein distance, the number of single character edits to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
     }
+    row[b.len()]
 }
 
-/// Extended version of cvar::IConsole with support for colored text
+/// Console used to collect the output of a single action invocation.
 ///
-/// See `IConsoleExt` for extra methods.
-/// This will usually be managed by a ConsoleWindow.
-pub struct ColoredConsole {
-    buf: Vec<TextSpan>,
+/// Text is gathered as the successful result, while the first error written takes precedence.
+#[derive(Default)]
+struct CaptureConsole {
+    out: String,
+    error: Option<ConsoleError>,
 }
 
-impl ColoredConsole {
-    pub fn write<S>(&mut self, text: S)
-    where
-        S: Into<TextSpan>,
-    {
-        self.buf.push(text.into());
+impl std::fmt::Write for CaptureConsole {
+    fn write_str(&mut self, s: &str) -> Result<(), std::fmt::Error> {
+        self.out.push_str(s);
+        Ok(())
     }
+}
 
-    pub fn writeln<S>(&mut self, text: S)
-    where
-        S: Into<TextSpan>,
-    {
-        let mut span = text.into();
-        span.text = span.text.trim_end().to_string();
-        if !span.text.is_empty() {
-            span.text.push_str("\n");
-            self.write(span);
+impl cvar::IConsole for CaptureConsole {
+    fn write_error(&mut self, err: &(dyn std::error::Error + 'static)) {
+        if self.error.is_none() {
+            self.error = Some(match err.downcast_ref::<ConsoleError>() {
+                Some(e) => e.clone(),
+                None => ConsoleError::Custom(err.to_string().into()),
+            });
         }
     }
 }
 
-impl IConsoleExt for ColoredConsole {
-    fn write(&mut self, text: &str) {
-        use std::fmt::Write;
-        let _ = self.write_str(&text);
+impl From<CaptureConsole> for ConsoleResult {
+    fn from(c: CaptureConsole) -> ConsoleResult {
+        match c.error {
+            Some(e) => e.into(),
+            None => c.out.into(),
+        }
     }
+}
 
-    fn write_result(&mut self, result: ConsoleResult) {
-        match &*result {
-            Ok(output) => {
-                self.writeln(output);
-            }
-            Err(e) => {
-                use cvar::IConsole;
-                self.write_error(e);
-            }
-        };
-    }
+/// cvar action whose handler returns a `ConsoleResult` instead of writing to a console.
+///
+/// Use `ResultAction` to construct. The returned value becomes the result of the command,
+/// which is convenient for simple query style commands. Use `cvar::Action` together with the
+/// `IConsoleExt` passed to `visit_mut_ext` if you need incremental or colored output.
+pub struct ResultAction<N, D, F> {
+    name: N,
+    desc: D,
+    f: F,
+}
 
-    fn write_colored(&mut self, c: [f32; 4], t: &str) {
-        self.write(TextSpan {
-            text: t.to_string(),
-            color: c,
-        });
+impl<N, D, F> cvar::INode for ResultAction<N, D, F>
+where
+    N: AsRef<str>,
+    D: AsRef<str>,
+    F: FnMut(&[&str]) -> ConsoleResult,
+{
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        self.desc.as_ref()
+    }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::Action(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::Action(self)
     }
 }
 
-impl std::fmt::Write for ColoredConsole {
-    fn write_str(&mut self, s: &str) -> Result<(), std::fmt::Error> {
-        self.write(s);
-        Ok(())
+impl<N, D, F> cvar::IAction for ResultAction<N, D, F>
+where
+    N: AsRef<str>,
+    D: AsRef<str>,
+    F: FnMut(&[&str]) -> ConsoleResult,
+{
+    fn invoke(&mut self, args: &[&str], console: &mut dyn cvar::IConsole) {
+        match (self.f)(args).0 {
+            Ok(out) => {
+                let _ = console.write_str(&out);
+            }
+            Err(e) => console.write_error(&e),
+        }
     }
 }
 
-impl cvar::IConsole for ColoredConsole {
-    fn write_error(&mut self, err: &(dyn std::error::Error + 'static)) {
-        self.writeln(ConsoleError::Custom(err.to_string().into()));
-    }
+/// Construct a ResultAction
+///
+/// Works like `cvar::Action`, but the closure returns the result of the command.
+#[allow(non_snake_case)]
+pub fn ResultAction<N, D, F>(name: N, desc: D, f: F) -> ResultAction<N, D, F>
+where
+    F: FnMut(&[&str]) -> ConsoleResult,
+{
+    ResultAction { name, desc, f }
 }
 
-/// imgui frontend for cvars.
-/// Call `build` during your rendering stage.
+/// cvar list holding everything an `IVisitExt` child adds, under the list's name.
 ///
-/// Will handle reading user input, dispatching commands, and displaying output.
-pub struct ConsoleWindow {
-    console: ColoredConsole,
-    prompt: ImString,
-    //history: Vec<String>,
+/// Use `ListExt` to construct from inside `visit_mut_ext`. The child's entries are then addressed
+/// by dotted paths such as `paddle.color`, so children can reuse names without colliding.
+pub struct ListExt<'a, N, D> {
+    name: N,
+    desc: D,
+    child: &'a mut dyn IVisitExt,
+    console: &'a mut dyn IConsoleExt,
 }
 
-impl ConsoleWindow {
-    pub fn new() -> Self {
-        ConsoleWindow {
-            console: ColoredConsole { buf: vec![] },
-            prompt: ImString::with_capacity(100),
-            //history: vec![],
-        }
+impl<'a, N: AsRef<str>, D: AsRef<str>> cvar::INode for ListExt<'a, N, D> {
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        self.desc.as_ref()
+    }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::List(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::List(self)
     }
 }
 
-impl Default for ConsoleWindow {
-    fn default() -> Self {
-        ConsoleWindow::new()
+impl<'a, N: AsRef<str>, D: AsRef<str>> cvar::IList for ListExt<'a, N, D> {
+    fn as_ivisit(&mut self) -> &mut dyn cvar::IVisit {
+        self
     }
 }
 
-impl ConsoleWindow {
-    pub fn clear(&mut self) {
-        self.console.buf.clear();
+impl<'a, N, D> cvar::IVisit for ListExt<'a, N, D> {
+    fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
+        self.child.visit_mut_ext(f, self.console);
     }
+}
 
-    pub fn write<S>(&mut self, text: S)
-    where
-        S: Into<TextSpan>,
-    {
-        self.console.write(text);
+/// Construct a ListExt
+///
+/// Works like `cvar::List`, for an `IVisitExt` child. Pass along the console given to
+/// `visit_mut_ext` so the child's commands can still write to it.
+#[allow(non_snake_case)]
+pub fn ListExt<'a, N, D>(
+    name: N,
+    desc: D,
+    child: &'a mut dyn IVisitExt,
+    console: &'a mut dyn IConsoleExt,
+) -> ListExt<'a, N, D> {
+    ListExt {
+        name,
+        desc,
+        child,
+        console,
     }
+}
 
-    pub fn writeln<S>(&mut self, text: S)
-    where
-        S: Into<TextSpan>,
-    {
-        self.console.writeln(text);
-    }
+/// Start of the description line listing the values of an `EnumProperty`.
+///
+/// cvar has no way to ask a property what it accepts, so completion finds them here.
+const VALUES_PREFIX: &str = "One of: ";
 
-    pub fn write_colored(&mut self, c: [f32; 4], t: &str) {
-        self.console.write_colored(c, t);
+/// cvar property holding one of a fixed set of strings.
+///
+/// Use `EnumProperty` to construct. Anything else is rejected by `set`, matching ignores case,
+/// and the allowed values are listed in help and offered by tab completion.
+pub struct EnumProperty<'a, N> {
+    name: N,
+    desc: String,
+    variable: &'a mut String,
+    default: &'a str,
+    variants: &'a [&'a str],
+}
+
+impl<'a, N: AsRef<str>> cvar::INode for EnumProperty<'a, N> {
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        &self.desc
     }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::Prop(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::Prop(self)
+    }
+}
 
-    pub fn draw_prompt(&mut self) {
-        self.write(TextSpan {
-            text: " > ".to_string(),
-            color: [0., 1., 1., 1.],
-        });
+impl<'a, N: AsRef<str>> cvar::IProperty for EnumProperty<'a, N> {
+    fn get(&self) -> String {
+        self.variable.clone()
+    }
+    fn set(&mut self, val: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self.variants.iter().find(|v| v.eq_ignore_ascii_case(val)) {
+            Some(v) => {
+                *self.variable = v.to_string();
+                Ok(())
+            }
+            None => Err(format!("expected one of {}", self.variants.join(", ")).into()),
+        }
     }
+    fn reset(&mut self) {
+        *self.variable = self.default.to_string();
+    }
+    fn default(&self) -> String {
+        self.default.to_string()
+    }
+}
 
-    pub fn build(&mut self, ui: &imgui::Ui, window: imgui::Window, root: &mut dyn IVisitExt) {
-        window
-            .size([520., 600.], imgui::Condition::FirstUseEver)
-            .build(ui, move || {
-                if ui.is_item_hovered() {
-                    ui.popup(im_str!("context_menu"), || {
-                        if imgui::MenuItem::new(im_str!("Close")).build(ui) {
-                            //self.close();
-                        }
-                    })
+/// Construct an EnumProperty
+///
+/// Works like `cvar::Property` for a `String`, limited to one of `variants`.
+#[allow(non_snake_case)]
+pub fn EnumProperty<'a, N, D>(
+    name: N,
+    desc: D,
+    variable: &'a mut String,
+    default: &'a str,
+    variants: &'a [&'a str],
+) -> EnumProperty<'a, N>
+where
+    D: AsRef<str>,
+{
+    EnumProperty {
+        name,
+        desc: format!(
+            "{}\n{}{}",
+            desc.as_ref(),
+            VALUES_PREFIX,
+            variants.join(", ")
+        ),
+        variable,
+        default,
+        variants,
+    }
+}
+
+/// Last line of the description of a `ReadOnlyProperty`, which is how help tells them apart.
+const READ_ONLY: &str = "(read-only)";
+
+pub(crate) fn is_read_only(desc: &str) -> bool {
+    desc.ends_with(READ_ONLY)
+}
+
+/// Returned by `set` on a `ReadOnlyProperty`.
+#[derive(Debug)]
+struct ReadOnlyError;
+
+impl std::fmt::Display for ReadOnlyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "read-only")
+    }
+}
+
+impl std::error::Error for ReadOnlyError {}
+
+/// cvar property which can be read but never set, for values such as FPS or entity counts.
+///
+/// Use `ReadOnlyProperty` to construct. `set` is rejected and `reset` does nothing.
+pub struct ReadOnlyProperty<'a, N, T> {
+    name: N,
+    desc: String,
+    variable: &'a T,
+}
+
+impl<'a, N: AsRef<str>, T: std::fmt::Display> cvar::INode for ReadOnlyProperty<'a, N, T> {
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        &self.desc
+    }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::Prop(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::Prop(self)
+    }
+}
+
+impl<'a, N: AsRef<str>, T: std::fmt::Display> cvar::IProperty for ReadOnlyProperty<'a, N, T> {
+    fn get(&self) -> String {
+        self.variable.to_string()
+    }
+    fn set(&mut self, _val: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Err(Box::new(ReadOnlyError))
+    }
+    fn reset(&mut self) {}
+    fn default(&self) -> String {
+        self.variable.to_string()
+    }
+}
+
+/// Construct a ReadOnlyProperty
+///
+/// Works like `cvar::Property`, but only for reading `variable`.
+#[allow(non_snake_case)]
+pub fn ReadOnlyProperty<'a, N, D, T>(
+    name: N,
+    desc: D,
+    variable: &'a T,
+) -> ReadOnlyProperty<'a, N, T>
+where
+    D: AsRef<str>,
+{
+    ReadOnlyProperty {
+        name,
+        desc: format!("{}\n{}", desc.as_ref(), READ_ONLY),
+        variable,
+    }
+}
+
+/// cvar property for a number which must stay within `min..=max`.
+///
+/// Use `ClampedProperty` to construct. Values outside the range are clamped to it, or rejected if
+/// `strict` is used. The range is listed in help.
+pub struct ClampedProperty<'a, N, T> {
+    name: N,
+    desc: String,
+    variable: &'a mut T,
+    default: T,
+    min: T,
+    max: T,
+    strict: bool,
+}
+
+impl<'a, N, T> ClampedProperty<'a, N, T> {
+    /// Reject values outside the range instead of clamping them.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+}
+
+impl<'a, N, T> cvar::INode for ClampedProperty<'a, N, T>
+where
+    N: AsRef<str>,
+    T: Copy + PartialOrd + std::str::FromStr + std::fmt::Display,
+    T::Err: std::fmt::Display,
+{
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        &self.desc
+    }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::Prop(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::Prop(self)
+    }
+}
+
+impl<'a, N, T> cvar::IProperty for ClampedProperty<'a, N, T>
+where
+    N: AsRef<str>,
+    T: Copy + PartialOrd + std::str::FromStr + std::fmt::Display,
+    T::Err: std::fmt::Display,
+{
+    fn get(&self) -> String {
+        self.variable.to_string()
+    }
+    fn set(&mut self, val: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let val = val.parse::<T>().map_err(|e| e.to_string())?;
+        let in_range = val >= self.min && val <= self.max;
+        if !in_range && self.strict {
+            return Err(format!("{} is outside {} to {}", val, self.min, self.max).into());
+        }
+        *self.variable = if val < self.min {
+            self.min
+        } else if val > self.max {
+            self.max
+        } else {
+            val
+        };
+        Ok(())
+    }
+    fn reset(&mut self) {
+        *self.variable = self.default;
+    }
+    fn default(&self) -> String {
+        self.default.to_string()
+    }
+}
+
+/// Start of the description line giving the range of a `ClampedProperty`.
+const RANGE_PREFIX: &str = "Range: ";
+
+/// The range listed in the description of a `ClampedProperty`, if there is one.
+#[cfg(feature = "window")]
+fn parse_range(desc: &str) -> Option<(f32, f32)> {
+    let line = desc.lines().find(|l| l.starts_with(RANGE_PREFIX))?;
+    let mut bounds = line[RANGE_PREFIX.len()..].split(" to ");
+    let min = bounds.next()?.trim().parse().ok()?;
+    let max = bounds.next()?.trim().parse().ok()?;
+    Some((min, max))
+}
+
+/// Construct a ClampedProperty
+///
+/// Works like `cvar::Property`, but keeps the value between `min` and `max`, inclusive.
+#[allow(non_snake_case)]
+pub fn ClampedProperty<'a, N, D, T>(
+    name: N,
+    desc: D,
+    variable: &'a mut T,
+    default: T,
+    min: T,
+    max: T,
+) -> ClampedProperty<'a, N, T>
+where
+    D: AsRef<str>,
+    T: std::fmt::Display,
+{
+    ClampedProperty {
+        name,
+        desc: format!("{}\n{}{} to {}", desc.as_ref(), RANGE_PREFIX, min, max),
+        variable,
+        default,
+        min,
+        max,
+        strict: false,
+    }
+}
+
+/// cvar property for a fixed size group of values, such as a position or a color.
+///
+/// Use `VecProperty` to construct. The values are set together as one space or comma separated
+/// argument, `pos "1 2 3"`, and anything with the wrong number of values is rejected.
+pub struct VecProperty<'a, N, T> {
+    name: N,
+    desc: String,
+    variable: &'a mut [T],
+    default: Vec<T>,
+}
+
+impl<'a, N, T> cvar::INode for VecProperty<'a, N, T>
+where
+    N: AsRef<str>,
+    T: Copy + std::str::FromStr + std::fmt::Display,
+    T::Err: std::fmt::Display,
+{
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        &self.desc
+    }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::Prop(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::Prop(self)
+    }
+}
+
+impl<'a, N, T> cvar::IProperty for VecProperty<'a, N, T>
+where
+    N: AsRef<str>,
+    T: Copy + std::str::FromStr + std::fmt::Display,
+    T::Err: std::fmt::Display,
+{
+    fn get(&self) -> String {
+        join_values(self.variable)
+    }
+    fn set(&mut self, val: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let parts = val
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        if parts.len() != self.variable.len() {
+            let msg = format!(
+                "Expected {} values, got {}",
+                self.variable.len(),
+                parts.len()
+            );
+            return Err(msg.into());
+        }
+        // parse everything first, so a bad value leaves the rest untouched
+        let mut values = vec![];
+        for part in parts {
+            values.push(part.parse::<T>().map_err(|e| format!("{}: {}", part, e))?);
+        }
+        self.variable.copy_from_slice(&values);
+        Ok(())
+    }
+    fn reset(&mut self) {
+        for (val, default) in self.variable.iter_mut().zip(&self.default) {
+            *val = *default;
+        }
+    }
+    fn default(&self) -> String {
+        join_values(&self.default)
+    }
+}
+
+fn join_values<T: std::fmt::Display>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Construct a VecProperty
+///
+/// Works like `cvar::Property` for an array such as `[f32; 3]`. `default` should be the same
+/// length as `variable`.
+#[allow(non_snake_case)]
+pub fn VecProperty<'a, N, D, T>(
+    name: N,
+    desc: D,
+    variable: &'a mut [T],
+    default: &[T],
+) -> VecProperty<'a, N, T>
+where
+    D: AsRef<str>,
+    T: Copy,
+{
+    VecProperty {
+        name,
+        desc: format!(
+            "{}\nSet all {} values at once, such as \"{}\"",
+            desc.as_ref(),
+            variable.len(),
+            (1..=variable.len())
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+        variable,
+        default: default.to_vec(),
+    }
+}
+
+/// cvar property holding a color, set by name or as hex.
+///
+/// Use `ColorProperty` to construct. The value reads back as `#rrggbb`, which is also what puts
+/// it in the color panel.
+pub struct ColorProperty<'a, N, D> {
+    name: N,
+    desc: D,
+    variable: &'a mut [f32; 4],
+    default: [f32; 4],
+}
+
+impl<'a, N, D> cvar::INode for ColorProperty<'a, N, D>
+where
+    N: AsRef<str>,
+    D: AsRef<str>,
+{
+    fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+    fn description(&self) -> &str {
+        self.desc.as_ref()
+    }
+    fn as_node_ref(&self) -> cvar::NodeRef<'_> {
+        cvar::NodeRef::Prop(self)
+    }
+    fn as_node_mut(&mut self) -> cvar::NodeMut<'_> {
+        cvar::NodeMut::Prop(self)
+    }
+}
+
+impl<'a, N, D> cvar::IProperty for ColorProperty<'a, N, D>
+where
+    N: AsRef<str>,
+    D: AsRef<str>,
+{
+    fn get(&self) -> String {
+        format_hex_color(*self.variable)
+    }
+    fn set(&mut self, val: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // CvarExt::set adds its own "Invalid value" in front
+        *self.variable = parse_color(val).map_err(|e| match e {
+            ConsoleError::InvalidValue(msg) => msg,
+            e => e.to_string(),
+        })?;
+        Ok(())
+    }
+    fn reset(&mut self) {
+        *self.variable = self.default;
+    }
+    fn default(&self) -> String {
+        format_hex_color(self.default)
+    }
+}
+
+/// Construct a ColorProperty
+#[allow(non_snake_case)]
+pub fn ColorProperty<'a, N, D>(
+    name: N,
+    desc: D,
+    variable: &'a mut [f32; 4],
+    default: [f32; 4],
+) -> ColorProperty<'a, N, D> {
+    ColorProperty {
+        name,
+        desc,
+        variable,
+        default,
+    }
+}
+
+fn find_with(
+    root: &mut dyn cvar::IVisit,
+    filter: &(dyn Fn(&str) -> bool),
+    format: &ValueFormat,
+) -> Result<Vec<TextSpan>, ConsoleError> {
+    let mut out = vec![];
+    cvar::console::walk(root, |path, node| {
+        if filter(path) {
+            node.details(path, &mut out, format);
+        }
+    });
+
+    if !out.is_empty() {
+        Ok(out)
+    } else {
+        Err(ConsoleError::NoResults)
+    }
+}
+
+/// Help for every node, with the contents of each list collapsed under a heading for that list.
+fn grouped_help(root: &mut dyn cvar::IVisit, format: &ValueFormat) -> Vec<TextSpan> {
+    let mut entries = vec![];
+    cvar::console::walk(root, |path, node| {
+        let mut details = vec![];
+        let is_list = if let CmdType::List = node.kind() {
+            details.push(node.description().into());
+            true
+        } else {
+            node.details(path, &mut details, format);
+            false
+        };
+        entries.push((path.to_string(), is_list, details));
+    });
+
+    // Each entry belongs to the deepest list whose path it extends
+    let lists = entries
+        .iter()
+        .filter(|(_, is_list, _)| *is_list)
+        .map(|(path, _, _)| path.clone())
+        .collect::<Vec<_>>();
+    let parent = |path: &str| {
+        lists
+            .iter()
+            .filter(|list| path.starts_with(&format!("{}.", list)))
+            .max_by_key(|list| list.len())
+            .cloned()
+    };
+    let parents = entries
+        .iter()
+        .map(|(path, _, _)| parent(path))
+        .collect::<Vec<_>>();
+
+    fn group(
+        entries: &[(String, bool, Vec<TextSpan>)],
+        parents: &[Option<String>],
+        list: Option<&String>,
+    ) -> Vec<TextSpan> {
+        let mut out = vec![];
+        for (i, (path, is_list, details)) in entries.iter().enumerate() {
+            if parents[i].as_ref() != list {
+                continue;
+            }
+            if *is_list {
+                let mut summary = path.clone();
+                let desc = spans_text(details);
+                if !desc.is_empty() {
+                    summary.push_str(&format!(": {}", desc));
                 }
+                out.push(TextSpan {
+                    color: LIST_COLOR,
+                    text: format!("{}\n", summary),
+                    detail: group(entries, parents, Some(path)),
+                    ..Default::default()
+                });
+            } else {
+                out.extend(details.iter().cloned());
+            }
+        }
+        out
+    }
+    group(&entries, &parents, None)
+}
+
+fn help_with(
+    root: &mut dyn cvar::IVisit,
+    var: &str,
+    format: &ValueFormat,
+) -> Result<Vec<TextSpan>, ConsoleError> {
+    let mut out = vec![];
+    cvar::console::find(root, var, |node| {
+        node.details(var, &mut out, format);
+    });
+
+    if !out.is_empty() {
+        Ok(out)
+    } else {
+        Err(ConsoleError::UnknownProperty)
+    }
+}
+
+/// How well `pattern` matches `candidate` as a subsequence, ignoring case, or `None` if it doesn't.
+///
+/// Higher is better. Each matched letter is worth a point, plus 8 when it directly follows the
+/// previous match or 6 when it starts a word (after `.`, `_`, `-` or at the very start), and every
+/// letter skipped over costs a point. So `pvel` scores well against `paddle.velocity`, and better
+/// still against `pvel`. Letters are matched as early as possible, which keeps the score the same
+/// for the same inputs, though not always the best one possible.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i32> {
+    let mut pattern = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut last: Option<usize> = None;
+    let mut prev: Option<char> = None;
+    for (i, c) in candidate.chars().flat_map(char::to_lowercase).enumerate() {
+        let wanted = match pattern.peek() {
+            Some(&wanted) => wanted,
+            None => break,
+        };
+        if c == wanted {
+            score += 1;
+            if last.map_or(false, |last| last + 1 == i) {
+                score += 8;
+            } else if prev.map_or(true, |p| p == '.' || p == '_' || p == '-') {
+                score += 6;
+            }
+            score -= (i - last.map_or(0, |last| last + 1)) as i32;
+            last = Some(i);
+            pattern.next();
+        }
+        prev = Some(c);
+    }
+    if pattern.peek().is_none() {
+        Some(score)
+    } else {
+        None
+    }
+}
+
+/// Like `find_with`, but the entries are ordered by `score`, best first, leaving out those it
+/// gives `None`. Equal scores keep their usual order.
+fn find_ranked(
+    root: &mut dyn cvar::IVisit,
+    score: &(dyn Fn(&str) -> Option<i32>),
+    format: &ValueFormat,
+) -> Result<Vec<TextSpan>, ConsoleError> {
+    let mut entries = vec![];
+    cvar::console::walk(root, |path, node| {
+        if let Some(score) = score(path) {
+            let mut details = vec![];
+            node.details(path, &mut details, format);
+            entries.push((score, details));
+        }
+    });
+    entries.sort_by(|a, b| b.0.cmp(&a.0));
+
+    if !entries.is_empty() {
+        Ok(entries
+            .into_iter()
+            .flat_map(|(_, details)| details)
+            .collect())
+    } else {
+        Err(ConsoleError::NoResults)
+    }
+}
+
+/// Help for a list and everything in it, indented by how deeply each entry is nested.
+fn list_help(
+    root: &mut dyn cvar::IVisit,
+    list: &str,
+    format: &ValueFormat,
+) -> Result<Vec<TextSpan>, ConsoleError> {
+    let mut out = help_with(root, list, format)?;
+    let prefix = format!("{}.", list);
+    cvar::console::walk(root, |path, node| {
+        if !path.starts_with(&prefix) {
+            return;
+        }
+        let indent = "  ".repeat(path[prefix.len()..].matches('.').count() + 1);
+        let mut entry = vec![];
+        node.details(path, &mut entry, format);
+        for span in &mut entry {
+            span.text = span.text.replace("\n\t", &format!("\n{}\t", indent));
+        }
+        out.push(indent.into());
+        out.extend(entry);
+    });
+    Ok(out)
+}
+
+/// A piece of text with attached metadata such as color
+///
+/// Construct manually, or convert from a `String` / `ConsoleError` if you want the default color.
+/// Everything besides the color and text is set with the `with_` methods, such as
+/// `TextSpan::from("spawn").with_command("spawn 10")`, so more can be added later on without
+/// breaking code which builds spans.
+#[derive(Debug, Clone)]
+pub struct TextSpan {
+    pub color: [f32; 4],
+    pub text: String,
+    /// Extra lines hidden under this span until it is expanded. Empty for plain text.
+    pub(crate) detail: Vec<TextSpan>,
+    pub(crate) role: SpanRole,
+    /// When the span was written. Filled in by `ColoredConsole::write` if left empty.
+    pub(crate) time: Option<Instant>,
+    /// Filled in behind the text, if set.
+    pub(crate) bg: Option<[f32; 4]>,
+    pub(crate) style: SpanStyle,
+    /// Put in the prompt when the span is clicked, making it a link. None for plain text.
+    pub(crate) command: Option<String>,
+}
+
+/// Emphasis for a span of text.
+///
+/// imgui draws every line in a single font, so these are imitated rather than real font faces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanStyle {
+    Normal,
+    /// Drawn twice, one pixel apart.
+    Bold,
+    /// Drawn with a line under it.
+    Underline,
+}
+
+/// What a span in the console output represents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanRole {
+    /// Anything written by a command. The default.
+    Output,
+    /// The prompt drawn in front of an entered command.
+    Prompt,
+    /// A command as it was entered.
+    Input,
+    /// An error from a command.
+    Error,
+}
+
+impl Default for TextSpan {
+    fn default() -> TextSpan {
+        TextSpan {
+            color: [1., 1., 1., 1.],
+            text: String::new(),
+            detail: vec![],
+            role: SpanRole::Output,
+            time: None,
+            bg: None,
+            style: SpanStyle::Normal,
+            command: None,
+        }
+    }
+}
+
+impl<T> From<T> for TextSpan
+where
+    T: Into<String>,
+{
+    fn from(t: T) -> TextSpan {
+        TextSpan {
+            text: t.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl TextSpan {
+    /// Hide `detail` under this span until it is expanded, like `write_collapsible` does.
+    pub fn with_detail(mut self, detail: Vec<TextSpan>) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    pub fn with_role(mut self, role: SpanRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Show the span as written at `time`, instead of when it reaches the console.
+    pub fn with_time(mut self, time: Instant) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Fill `color` in behind the text.
+    pub fn with_background(mut self, color: [f32; 4]) -> Self {
+        self.bg = Some(color);
+        self
+    }
+
+    pub fn with_style(mut self, style: SpanStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Make the span a link, which puts `cmd` in the prompt when clicked.
+    pub fn with_command(mut self, cmd: &str) -> Self {
+        self.command = Some(cmd.to_string());
+        self
+    }
+
+    /// Spans hidden under this one until it is expanded.
+    pub fn detail(&self) -> &[TextSpan] {
+        &self.detail
+    }
+
+    pub fn role(&self) -> SpanRole {
+        self.role
+    }
+
+    /// When the span was written, once it has reached a console.
+    pub fn time(&self) -> Option<Instant> {
+        self.time
+    }
+
+    pub fn background(&self) -> Option<[f32; 4]> {
+        self.bg
+    }
+
+    pub fn style(&self) -> SpanStyle {
+        self.style
+    }
+
+    /// What clicking the span puts in the prompt, if it is a link.
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_ref().map(|cmd| cmd.as_str())
+    }
+
+    /// Split text with inline colors such as `"[#ff0000]error[/] ok"` into spans.
+    ///
+    /// `[#rrggbb]`, `[#rrggbbaa]` or a color name like `[red]` starts a color and `[/]` goes back
+    /// to the one before it.
+    /// Anything else in brackets, or a `[/]` with no color to end, is kept as text.
+    pub fn parse_markup(text: &str) -> Vec<TextSpan> {
+        let mut spans = vec![];
+        let mut colors = vec![TextSpan::default().color];
+        let mut current = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('[') {
+            let tag = rest[start..]
+                .find(']')
+                .map(|end| &rest[start + 1..start + end]);
+            let color = tag.and_then(|tag| parse_color(tag).ok());
+            let close = tag == Some("/") && colors.len() > 1;
+            current.push_str(&rest[..start]);
+            match tag {
+                Some(tag) if color.is_some() || close => {
+                    if !current.is_empty() {
+                        spans.push(TextSpan {
+                            color: colors[colors.len() - 1],
+                            text: std::mem::replace(&mut current, String::new()),
+                            ..Default::default()
+                        });
+                    }
+                    match color {
+                        Some(color) => colors.push(color),
+                        None => {
+                            colors.pop();
+                        }
+                    }
+                    rest = &rest[start + tag.len() + 2..];
+                }
+                _ => {
+                    current.push('[');
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        current.push_str(rest);
+        if !current.is_empty() {
+            spans.push(TextSpan {
+                color: colors[colors.len() - 1],
+                text: current,
+                ..Default::default()
+            });
+        }
+        spans
+    }
+}
+
+impl From<ConsoleError> for TextSpan {
+    fn from(e: ConsoleError) -> TextSpan {
+        TextSpan {
+            color: [1., 0., 0., 1.],
+            text: e.to_string(),
+            role: SpanRole::Error,
+            ..Default::default()
+        }
+    }
+}
+
+/// Extended version of cvar::IConsole with support for colored text
+///
+/// See `ColoredConsole` for a concrete implementation
+pub trait IConsoleExt: cvar::IConsole {
+    fn write(&mut self, text: &str);
+    fn write_result(&mut self, result: ConsoleResult);
+    fn write_colored(&mut self, c: [f32; 4], t: &str);
+
+    /// Write text with inline colors, as described in `TextSpan::parse_markup`.
+    fn write_markup(&mut self, text: &str) {
+        for span in TextSpan::parse_markup(text) {
+            self.write_colored(span.color, &span.text);
+        }
+    }
+
+    /// Write a summary line which can be expanded to show `detail`.
+    ///
+    /// Consoles without support for this show everything at once.
+    fn write_collapsible(&mut self, summary: &str, detail: Vec<TextSpan>) {
+        self.write(&format!("{}\n", summary.trim_end()));
+        for span in detail {
+            if span.detail.is_empty() {
+                self.write_colored(span.color, &span.text);
+            } else {
+                self.write_collapsible(&span.text, span.detail);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for TextSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Extended version of cvar::IConsole with support for colored text
+///
+/// See `IConsoleExt` for extra methods.
+/// This will usually be managed by a ConsoleWindow.
+pub struct ColoredConsole {
+    buf: VecDeque<TextSpan>,
+    /// The first error written, so a command that reports its own errors still counts as failed.
+    error: Option<String>,
+    hook: Option<OutputHook>,
+}
+
+type OutputHook = Box<dyn FnMut(&TextSpan) + Send + Sync>;
+
+type ChangeHook = Box<dyn FnMut(&str, &str) + Send + Sync>;
+
+impl ColoredConsole {
+    pub fn write<S>(&mut self, text: S)
+    where
+        S: Into<TextSpan>,
+    {
+        let mut span = text.into();
+        span.time.get_or_insert_with(Instant::now);
+        if let Some(hook) = &mut self.hook {
+            hook(&span);
+        }
+        self.buf.push_back(span);
+    }
+
+    pub fn writeln<S>(&mut self, text: S)
+    where
+        S: Into<TextSpan>,
+    {
+        let mut span = text.into();
+        span.text = span.text.trim_end().to_string();
+        if !span.text.is_empty() {
+            span.text.push_str("\n");
+            self.write(span);
+        }
+    }
+}
+
+impl IConsoleExt for ColoredConsole {
+    fn write(&mut self, text: &str) {
+        use std::fmt::Write;
+        let _ = self.write_str(&text);
+    }
+
+    fn write_result(&mut self, result: ConsoleResult) {
+        match &*result {
+            Ok(output) => {
+                self.writeln(output);
+            }
+            Err(e) => {
+                use cvar::IConsole;
+                self.write_error(e);
+            }
+        };
+    }
+
+    fn write_colored(&mut self, c: [f32; 4], t: &str) {
+        self.write(TextSpan {
+            text: t.to_string(),
+            color: c,
+            ..Default::default()
+        });
+    }
+
+    fn write_collapsible(&mut self, summary: &str, detail: Vec<TextSpan>) {
+        self.write(TextSpan {
+            text: format!("{}\n", summary.trim_end()),
+            detail,
+            ..Default::default()
+        });
+    }
+}
+
+impl std::fmt::Write for ColoredConsole {
+    fn write_str(&mut self, s: &str) -> Result<(), std::fmt::Error> {
+        self.write(s);
+        Ok(())
+    }
+}
+
+impl cvar::IConsole for ColoredConsole {
+    fn write_error(&mut self, err: &(dyn std::error::Error + 'static)) {
+        // Never let an error disappear just because its message is empty
+        let mut msg = err.to_string();
+        if msg.trim().is_empty() {
+            msg = error_label(err).to_string();
+        }
+        self.error.get_or_insert_with(|| msg.clone());
+        self.writeln(ConsoleError::Custom(msg.into()));
+    }
+}
+
+/// Short name for the kind of `err`, shown in place of an empty message.
+fn error_label(err: &(dyn std::error::Error + 'static)) -> &'static str {
+    match err.downcast_ref::<ConsoleError>() {
+        Some(ConsoleError::UnknownProperty) => "Unknown property",
+        Some(ConsoleError::UnknownCommand) => "Unknown command",
+        Some(ConsoleError::InvalidValue(_)) => "Invalid value",
+        Some(ConsoleError::InvalidUsage(_)) => "Invalid usage",
+        Some(ConsoleError::NoResults) => "No results",
+        Some(ConsoleError::Unimplemented) => "Unimplemented",
+        Some(ConsoleError::Custom(_)) => "Command failed",
+        None => "Error",
+    }
+}
+
+/// Group spans into display lines, as ranges of `spans`.
+///
+/// A line ends with a span containing a newline. Collapsible spans are always a line of their own.
+fn line_ranges<'a, I>(spans: I) -> Vec<std::ops::Range<usize>>
+where
+    I: IntoIterator<Item = &'a TextSpan>,
+{
+    let mut lines = vec![];
+    let mut start = 0;
+    let mut len = 0;
+    for (i, span) in spans.into_iter().enumerate() {
+        len = i + 1;
+        if !span.detail.is_empty() && start < i {
+            lines.push(start..i);
+            start = i;
+        }
+        if span.text.contains('\n') || !span.detail.is_empty() {
+            lines.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+    if start < len {
+        lines.push(start..len);
+    }
+    lines
+}
+
+/// A span as a JSON object, for `export_json`.
+fn span_json(span: &TextSpan, started: Instant) -> String {
+    let role = match span.role {
+        SpanRole::Output => "output",
+        SpanRole::Prompt => "prompt",
+        SpanRole::Input => "input",
+        SpanRole::Error => "error",
+    };
+    let time = match span.time {
+        Some(time) if time > started => {
+            let since = time - started;
+            format!("{}.{:03}", since.as_secs(), since.subsec_millis())
+        }
+        Some(_) => "0".to_string(),
+        None => "null".to_string(),
+    };
+    let [r, g, b, a] = span.color;
+    let mut out = format!(
+        "{{\"role\": \"{}\", \"text\": {}, \"color\": [{}, {}, {}, {}], \"time\": {}",
+        role,
+        json_string(&span.text),
+        r,
+        g,
+        b,
+        a,
+        time
+    );
+    if !span.detail.is_empty() {
+        let detail = span
+            .detail
+            .iter()
+            .map(|d| span_json(d, started))
+            .collect::<Vec<_>>();
+        out.push_str(&format!(", \"detail\": [{}]", detail.join(", ")));
+    }
+    out.push('}');
+    out
+}
+
+/// Quote and escape `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
 
-                let clear = ui.button(im_str!("Clear"), [0., 0.]);
-                ui.same_line(0.);
-                let copy = ui.button(im_str!("Copy"), [0., 0.]);
-                ui.separator();
-
-                let footer_height_to_reserve = 1.5 * ui.frame_height_with_spacing();
-                let child = imgui::ChildWindow::new(imgui::Id::Str("scrolling"))
-                    .size([0., -footer_height_to_reserve])
-                    .horizontal_scrollbar(true);
-                child.build(ui, || {
-                    if clear {
-                        self.clear();
-                    }
-                    let buf = &mut self.console.buf;
-                    if copy {
-                        ui.set_clipboard_text(&ImString::new(
-                            buf.iter()
-                                .map(|l| l.to_string())
-                                .collect::<Vec<String>>()
-                                .join("\n"),
-                        ));
-                    }
+/// Text of a span including anything collapsed under it.
+fn span_text(span: &TextSpan, out: &mut String) {
+    out.push_str(&span.text);
+    for detail in &span.detail {
+        span_text(detail, out);
+    }
+}
 
-                    let style = ui.push_style_var(imgui::StyleVar::ItemSpacing([0., 0.]));
+/// Colors which can be given by name, wherever a color is parsed.
+const NAMED_COLORS: &[(&str, [f32; 4])] = &[
+    ("white", [1., 1., 1., 1.]),
+    ("black", [0., 0., 0., 1.]),
+    ("gray", [0.5, 0.5, 0.5, 1.]),
+    ("red", [1., 0., 0., 1.]),
+    ("green", [0., 1., 0., 1.]),
+    ("blue", [0., 0., 1., 1.]),
+    ("yellow", [1., 1., 0., 1.]),
+    ("cyan", [0., 1., 1., 1.]),
+    ("magenta", [1., 0., 1., 1.]),
+    ("orange", [1., 0.5, 0., 1.]),
+];
 
-                    for span in buf {
-                        /*if span.text.contains("\r") {
-                            let pos = ui.cursor_pos();
-                            ui.set_cursor_pos([0., pos[1]]);
-                        }*/
-                        ui.text_colored(span.color, &span.text);
-                        if !span.text.contains('\n') {
-                            ui.same_line(0.);
-                        }
-                    }
+/// Parse a color name such as `red`, or `#rrggbb` / `#rrggbbaa`.
+pub fn parse_color(text: &str) -> Result<[f32; 4], ConsoleError> {
+    if let Some(color) = parse_hex_color(text) {
+        return Ok(color);
+    }
+    let name = text.trim().to_lowercase();
+    match NAMED_COLORS.iter().find(|(n, _)| *n == name) {
+        Some((_, color)) => Ok(*color),
+        None => {
+            let names = NAMED_COLORS.iter().map(|(n, _)| *n).collect::<Vec<_>>();
+            Err(ConsoleError::InvalidValue(format!(
+                "Unknown color '{}', use #rrggbb or one of: {}",
+                text,
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+/// Parse `#rrggbb` or `#rrggbbaa` into a color.
+fn parse_hex_color(text: &str) -> Option<[f32; 4]> {
+    if !text.starts_with('#') {
+        return None;
+    }
+    let hex = &text[1..];
+    if (hex.len() != 6 && hex.len() != 8) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut color = [1.; 4];
+    for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *c = f32::from(u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?) / 255.;
+    }
+    Some(color)
+}
 
-                    style.pop(ui);
+/// Write a color as `#rrggbb`, or `#rrggbbaa` if it isn't opaque.
+fn format_hex_color(color: [f32; 4]) -> String {
+    let byte = |c: f32| (c.max(0.).min(1.) * 255.).round() as u8;
+    let [r, g, b, a] = color;
+    if byte(a) == 255 {
+        format!("#{:02x}{:02x}{:02x}", byte(r), byte(g), byte(b))
+    } else {
+        format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            byte(r),
+            byte(g),
+            byte(b),
+            byte(a)
+        )
+    }
+}
 
-                    if ui.scroll_y() >= ui.scroll_max_y() {
-                        ui.set_scroll_here_y_with_ratio(1.0);
-                    }
-                });
+/// Human readable duration such as `1h 23m 4s`.
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{}h {}m {}s", hours, mins, secs)
+    } else if mins > 0 {
+        format!("{}m {}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// A short span of time in milliseconds, such as `12.34 ms`.
+fn format_elapsed(duration: Duration) -> String {
+    let millis = duration.as_secs() as f64 * 1000. + f64::from(duration.subsec_nanos()) / 1e6;
+    format!("{:.2} ms", millis)
+}
+
+/// Shell style wildcard match, where `*` is any run of characters and `?` any single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Longest prefix shared by all the words, ignoring case. Taken from the first word.
+#[cfg(feature = "window")]
+fn common_prefix(words: &[String]) -> &str {
+    let first = match words.first() {
+        Some(first) => first,
+        None => return "",
+    };
+    let mut len = first.len();
+    for word in &words[1..] {
+        len = first
+            .char_indices()
+            .zip(word.chars())
+            .take_while(|((_, a), b)| a.to_lowercase().eq(b.to_lowercase()))
+            .map(|((i, a), _)| i + a.len_utf8())
+            .last()
+            .unwrap_or(0)
+            .min(len);
+    }
+    &first[..len]
+}
+
+/// Changes to the window itself asked for by builtin commands.
+///
+/// Commands only have access to the config, so these are applied once dispatch finishes.
+#[derive(Debug)]
+enum WindowRequest {
+    Clear,
+    ResetState,
+    Watch(String),
+    Unwatch(String),
+    Alias(String, String),
+    Unalias(String),
+    ListAliases,
+    /// Add the text given to `set_about_text` after the crate version.
+    About,
+    Wait(usize),
+    /// A script to run, and whether to stop at its first failing command.
+    Source(String, bool),
+}
+
+/// Everything produced by running a single command line.
+struct Dispatched {
+    result: ConsoleResult,
+    /// The result is the value of a property, which is formatted before it is shown.
+    get: bool,
+    console: ColoredConsole,
+    requests: Vec<WindowRequest>,
+    /// Properties the command changed, with their new values.
+    changes: Vec<(String, String)>,
+}
+
+/// Run a single command line against `root`, including the builtin commands.
+///
+/// Returns the result along with anything the command wrote to its console.
+fn dispatch(root: &mut dyn IVisitExt, line: &str, context: Context) -> Dispatched {
+    let mut root = VisitMutExt(|f, console| {
+        root.visit_mut_ext(f, console);
+    });
+    root.context = context.clone();
+
+    let line = match substitute(&mut root, line) {
+        Ok(line) => line,
+        Err(e) => {
+            return Dispatched {
+                result: e.into(),
+                get: false,
+                console: root.console,
+                requests: root.requests,
+                changes: root.changes,
+            }
+        }
+    };
+    let words = tokenize(&line);
+    let mut parts = words.iter().map(|s| s.as_str());
+    let cmd = parts.next().unwrap_or("");
+    let args = parts.collect::<Vec<_>>();
+
+    // Builtins always work in any case, as the banner tells people to type HELP
+    let canonical = match root.cmdtype(cmd) {
+        CmdType::NotFound if context.ignore_case => root.resolve_case(cmd),
+        CmdType::NotFound => VisitMutExt(|_, _| {}).resolve_case(cmd),
+        _ => None,
+    };
+    let cmd = canonical.as_ref().map_or(cmd, |c| c.as_str());
+
+    let mut get = false;
+    if let CmdType::Prop = root.cmdtype(cmd) {
+        get = args.is_empty();
+    }
+
+    let result = if context.catch_panics {
+        // the config is only borrowed, so anything left half changed stays visible afterwards,
+        // which is the caveat documented on `set_catch_panics`
+        let exec = std::panic::AssertUnwindSafe(|| root.exec(cmd, args));
+        std::panic::catch_unwind(exec).unwrap_or_else(|payload| {
+            let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+                msg.to_string()
+            } else if let Some(msg) = payload.downcast_ref::<String>() {
+                msg.clone()
+            } else {
+                "unknown error".to_string()
+            };
+            ConsoleError::Custom(format!("command panicked: {}", msg).into()).into()
+        })
+    } else {
+        root.exec(cmd, args)
+    };
+    Dispatched {
+        result,
+        get,
+        console: root.console,
+        requests: root.requests,
+        changes: root.changes,
+    }
+}
+
+/// Split `cmd > file` or `cmd >> file` at the first `>` outside of quotes.
+///
+/// Returns the command, the file with any quotes removed, and whether to append to it.
+fn split_redirect(line: &str) -> Option<(&str, String, bool)> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '>') => {
+                let rest = &line[i + 1..];
+                let append = rest.starts_with('>');
+                let rest = if append { &rest[1..] } else { rest };
+                let file = tokenize(rest).into_iter().next().unwrap_or_default();
+                return Some((&line[..i], file, append));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split a line on every `;` outside of quotes, dropping empty commands.
+fn split_commands(line: &str) -> Vec<&str> {
+    let mut cmds = vec![];
+    let mut start = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (_, '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, ';') => {
+                cmds.push(&line[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    cmds.push(&line[start..]);
+    cmds.retain(|cmd| !cmd.trim().is_empty());
+    cmds
+}
 
-                ui.separator();
-                let mut reclaim_focus = false;
-                let input = imgui::InputText::new(ui, im_str!("cmd"), &mut self.prompt)
-                    .enter_returns_true(true)
-                    //.callback_completion(true)
-                    //.callback_history(true)
-                    .build();
-                if input {
-                    self.draw_prompt();
-                    self.write(&format!("{}\n", self.prompt));
-                    self.run_cmd(root, self.prompt.to_string());
-                    self.prompt.clear();
-                    reclaim_focus = true;
+/// Split a command line into words.
+///
+/// Words are separated by whitespace. Single or double quotes keep spaces inside a word, and a
+/// backslash escapes the next character, even one outside ASCII. An unterminated quote runs to
+/// the end of the line.
+pub fn tokenize(input: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = input.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (_, '\\') => {
+                word.push(chars.next().unwrap_or('\\'));
+                in_word = true;
+            }
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::replace(&mut word, String::new()));
+                    in_word = false;
                 }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Split help entries into pages of about `lines` lines, never breaking an entry apart.
+///
+/// Everything is one page if `lines` is 0.
+fn paginate(spans: Vec<TextSpan>, lines: usize) -> Vec<Vec<TextSpan>> {
+    let mut pages = vec![vec![]];
+    let mut count = 0;
+    for span in spans {
+        let ends_entry = span.text.ends_with('\n') || !span.detail.is_empty();
+        count += if span.detail.is_empty() {
+            span.text.matches('\n').count()
+        } else {
+            1
+        };
+        if let Some(page) = pages.last_mut() {
+            page.push(span);
+        }
+        if lines > 0 && count >= lines && ends_entry {
+            pages.push(vec![]);
+            count = 0;
+        }
+    }
+    if pages.len() > 1 && pages.last().map_or(false, |page| page.is_empty()) {
+        pages.pop();
+    }
+    pages
+}
 
-                ui.set_item_default_focus();
-                if reclaim_focus {
-                    ui.set_keyboard_focus_here(imgui::FocusedWidget::Previous);
+/// Write help entries in their own colors, or the error that stopped them being found.
+fn write_spans(console: &mut dyn IConsoleExt, spans: Result<Vec<TextSpan>, ConsoleError>) {
+    match spans {
+        Ok(spans) => {
+            for span in spans {
+                if span.detail.is_empty() {
+                    console.write_colored(span.color, &span.text);
+                } else {
+                    console.write_collapsible(&span.text, span.detail);
                 }
-            });
+            }
+        }
+        Err(e) => console.write_result(e.into()),
     }
+}
 
-    /*pub fn close(&mut self,) {
-        use cvar::IConsole;
-        console.write_error(&ConsoleError::Unimplemented);
-    }*/
+/// Quote `arg` if needed so that `tokenize` reads it back as one word.
+fn quote_arg(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\' || c == ';');
+    if plain {
+        return arg.to_string();
+    }
+    let mut out = String::from("\"");
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+    out
+}
+
+/// The command line given as the arguments of `alias` or `bind`.
+///
+/// A single argument is the whole line, so a quoted `"a; b"` can hold several commands. Otherwise
+/// each word is quoted again where needed, so `name "Big Bob"` still has one argument.
+pub(crate) fn join_command(args: &[&str]) -> String {
+    match args {
+        [line] => line.to_string(),
+        _ => args
+            .iter()
+            .map(|arg| quote_arg(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
 
-    pub fn run_cmd(&mut self, root: &mut dyn IVisitExt, cmd: String) {
-        let mut parts = cmd.split(' '); // TODO: shellesc
-        let cmd = parts.next().unwrap_or("");
-        let args = parts.collect::<Vec<_>>();
+/// The quote `text` leaves open, if it ends inside one, read the same way as `tokenize` does.
+fn open_quote(text: &str) -> Option<char> {
+    let mut quote = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (_, '\\') => {
+                chars.next();
+            }
+            (Some(q), c) if c == q => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            _ => {}
+        }
+    }
+    quote
+}
 
-        let mut root = VisitMutExt(|f, console| {
-            root.visit_mut_ext(f, console);
-        });
+/// Replace every `$(var)` in `line` with the current value of that property.
+///
+/// Each value is quoted so it stays a single argument, whatever spaces or quotes it holds.
+/// Write `\$(` for a literal `$(`.
+fn substitute(root: &mut dyn cvar::IVisit, line: &str) -> Result<String, ConsoleError> {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find("$(") {
+        if rest[..start].ends_with('\\') {
+            out.push_str(&rest[..start - 1]);
+            out.push_str("$(");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        let end = match rest[start..].find(')') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let var = &rest[start + 2..end];
+        match cvar::console::get(root, var) {
+            Some(val) => {
+                out.push_str(&rest[..start]);
+                match open_quote(&out) {
+                    None => out.push_str(&quote_arg(&val)),
+                    // already quoted, so only what would end the quote needs escaping
+                    Some(q) => {
+                        for c in val.chars() {
+                            if c == q || c == '\\' {
+                                out.push('\\');
+                            }
+                            out.push(c);
+                        }
+                    }
+                }
+            }
+            None => {
+                let msg = format!("Unknown property in $({})", var);
+                return Err(ConsoleError::Custom(msg.into()));
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
 
-        let result = root.exec(cmd, args);
-        self.console.write_result(result);
-        self.console.buf.append(&mut root.console.buf);
+/// Run a command line without a window, returning everything it printed as the result.
+///
+/// Colors are dropped. Useful for tests, scripts, or anything else that isn't drawing the console.
+pub fn exec_capture(root: &mut dyn IVisitExt, line: &str) -> ConsoleResult {
+    let Dispatched {
+        result, console, ..
+    } = dispatch(root, line, Context::default());
+    match result.0 {
+        Ok(mut text) => {
+            // the result first, then anything written, the same order the console shows them in
+            if !text.is_empty() && !text.ends_with('\n') && !console.buf.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&spans_text(&console.buf));
+            text.into()
+        }
+        Err(e) => e.into(),
     }
 }
 
@@ -541,45 +2377,545 @@ pub trait IVisitExt {
 pub struct VisitMutExt<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> {
     closure: F,
     console: ColoredConsole,
+    context: Context,
+    requests: Vec<WindowRequest>,
+    changes: Vec<(String, String)>,
 }
 
 impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> VisitMutExt<F> {
     pub fn new(closure: F) -> Self {
         VisitMutExt {
             closure,
-            console: ColoredConsole { buf: vec![] },
+            console: ColoredConsole {
+                buf: VecDeque::new(),
+                error: None,
+                hook: None,
+            },
+            context: Context::default(),
+            requests: vec![],
+            changes: vec![],
+        }
+    }
+
+    /// `CvarExt::set`, noting the new value for `Console::on_change` if it changed.
+    pub fn set(&mut self, var: &str, val: &str) -> ConsoleResult {
+        self.track(var, |root| CvarExt::set(root, var, val))
+    }
+
+    /// `CvarExt::reset`, noting the new value for `Console::on_change` if it changed.
+    pub fn reset(&mut self, var: &str) -> ConsoleResult {
+        self.track(var, |root| CvarExt::reset(root, var))
+    }
+
+    /// `CvarExt::reset_all`, noting every value it changed for `Console::on_change`.
+    pub fn reset_all(&mut self) -> ConsoleResult {
+        let mut count = 0;
+        let mut changes = vec![];
+        cvar::console::walk(self, |path, node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                let before = prop.get();
+                prop.reset();
+                if prop.get() != before {
+                    changes.push((path.to_string(), prop.get()));
+                }
+                count += 1;
+            }
+        });
+        self.changes.extend(changes);
+        format!("Reset {} properties", count).into()
+    }
+
+    /// `CvarExt::exec`, with properties set through `set` so the change is noted.
+    pub fn exec(&mut self, cmd: &str, args: Vec<&str>) -> ConsoleResult {
+        match (self.cmdtype(cmd), args.get(0)) {
+            (CmdType::Prop, Some(val)) => self.set(cmd, val),
+            _ => CvarExt::exec(self, cmd, args),
+        }
+    }
+
+    /// Run `change`, then note the new value of `var` if it is different from before.
+    fn track<R>(&mut self, var: &str, change: impl FnOnce(&mut Self) -> R) -> R {
+        let before = cvar::console::get(self, var);
+        let out = change(self);
+        if let Some(after) = cvar::console::get(self, var) {
+            if before.as_ref() != Some(&after) {
+                self.changes.push((var.to_string(), after));
+            }
         }
+        out
     }
 
     pub fn cmd_help(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let format = self.context.format;
+        let page = match args.get(0) {
+            None => 1,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(page) if page > 0 => page,
+                _ => {
+                    // a list shows everything in it, anything else just itself
+                    let out = if let CmdType::List = self.cmdtype(arg) {
+                        list_help(self, arg, &format)
+                    } else {
+                        help_with(self, arg, &format)
+                    };
+                    write_spans(console, out);
+                    return;
+                }
+            },
+        };
+
+        let spans = grouped_help(self, &format);
+        if spans.is_empty() {
+            console.write_result(ConsoleError::NoResults.into());
+            return;
+        }
+        let mut pages = paginate(spans, self.context.help_page_lines);
+        let count = pages.len();
+        if page > count {
+            let e = ConsoleError::InvalidValue(format!("there are only {} pages of help", count));
+            console.write_result(e.into());
+            return;
+        }
+        write_spans(console, Ok(pages.swap_remove(page - 1)));
+        if page < count {
+            console.write_colored(
+                DIM_COLOR,
+                &format!(
+                    "-- more -- (page {} of {}, type help {} for the next)\n",
+                    page,
+                    count,
+                    page + 1
+                ),
+            );
+        }
+    }
+
+    pub fn cmd_find(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let format = self.context.format;
+        let out = match (args.get(0), args.get(1)) {
+            (Some(&"-r"), Some(pattern)) => match regex::Regex::new(pattern) {
+                Ok(re) => find_with(
+                    self,
+                    &|path: &str| re.is_match(path) && path != "find",
+                    &format,
+                ),
+                Err(e) => Err(ConsoleError::InvalidValue(e.to_string())),
+            },
+            (Some(var), _) if *var != "-r" => {
+                // every term has to match, except those starting with - which must not
+                let (exclude, include): (Vec<&str>, Vec<&str>) = args
+                    .iter()
+                    .cloned()
+                    .partition(|term| term.len() > 1 && term.starts_with('-'));
+                let exclude = exclude.iter().map(|term| &term[1..]).collect::<Vec<_>>();
+                if self.context.fuzzy {
+                    // a term found as is still beats one only found as a subsequence
+                    find_ranked(
+                        self,
+                        &|path: &str| {
+                            if path == "find" || exclude.iter().any(|term| path.contains(term)) {
+                                return None;
+                            }
+                            include.iter().try_fold(0, |total, term| {
+                                let bonus = if path.contains(term) { 100 } else { 0 };
+                                fuzzy_score(term, path).map(|score| total + score + bonus)
+                            })
+                        },
+                        &format,
+                    )
+                } else {
+                    find_with(
+                        self,
+                        &|path: &str| {
+                            include.iter().all(|term| path.contains(term))
+                                && !exclude.iter().any(|term| path.contains(term))
+                                && path != "find"
+                        },
+                        &format,
+                    )
+                }
+            }
+            _ => Err(ConsoleError::InvalidUsage(
+                "find <name> or find -r <regex>".to_string(),
+            )),
+        };
+        write_spans(console, out);
+    }
+
+    pub fn cmd_info(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = {
+            if let Some(var) = args.get(0) {
+                help_with(self, var, &ValueFormat::default())
+            } else {
+                Err(ConsoleError::InvalidUsage("info <var>".to_string()))
+            }
+        };
+        write_spans(console, out);
+    }
+
+    pub fn cmd_watch(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = match args.get(0) {
+            Some(var) => match &*self.get(var) {
+                Ok(_) => {
+                    self.requests.push(WindowRequest::Watch(var.to_string()));
+                    "".into()
+                }
+                Err(e) => e.clone().into(),
+            },
+            None => ConsoleError::InvalidUsage("watch <var>".to_string()).into(),
+        };
+        console.write_result(out);
+    }
+
+    pub fn cmd_unwatch(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        match args.get(0) {
+            Some(var) => self.requests.push(WindowRequest::Unwatch(var.to_string())),
+            None => {
+                console.write_result(ConsoleError::InvalidUsage("unwatch <var>".to_string()).into())
+            }
+        }
+    }
+
+    pub fn cmd_alias(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        match args.get(0) {
+            None => self.requests.push(WindowRequest::ListAliases),
+            Some(_) if args.len() < 2 => console.write_result(
+                ConsoleError::InvalidUsage("alias <name> <commands>".to_string()).into(),
+            ),
+            Some(name) => match self.cmdtype(name) {
+                CmdType::NotFound => {
+                    let cmd = join_command(&args[1..]);
+                    self.requests
+                        .push(WindowRequest::Alias(name.to_string(), cmd));
+                }
+                _ => console.write_result(
+                    ConsoleError::InvalidValue(format!("{} is already a command", name)).into(),
+                ),
+            },
+        }
+    }
+
+    pub fn cmd_unalias(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        match args.get(0) {
+            Some(name) => self.requests.push(WindowRequest::Unalias(name.to_string())),
+            None => console
+                .write_result(ConsoleError::InvalidUsage("unalias <name>".to_string()).into()),
+        }
+    }
+
+    pub fn cmd_toggle(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let var = match args.get(0) {
+            Some(var) => var,
+            None => {
+                console.write_result(ConsoleError::InvalidUsage("toggle <var>".to_string()).into());
+                return;
+            }
+        };
+        let out = match &*self.get(var) {
+            Ok(val) => {
+                let flipped = match val.trim() {
+                    "true" => "false",
+                    "false" => "true",
+                    "1" => "0",
+                    "0" => "1",
+                    _ => {
+                        let msg = format!("{} is {}, not true or false", var, val);
+                        console.write_result(ConsoleError::InvalidValue(msg).into());
+                        return;
+                    }
+                };
+                match &*self.set(var, flipped) {
+                    Ok(_) => format!("{} is now {}", var, flipped).into(),
+                    Err(e) => e.clone().into(),
+                }
+            }
+            Err(e) => e.clone().into(),
+        };
+        console.write_result(out);
+    }
+
+    pub fn cmd_inc(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = self.nudge(args, false);
+        console.write_result(out);
+    }
+
+    pub fn cmd_dec(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = self.nudge(args, true);
+        console.write_result(out);
+    }
+
+    /// Add (or subtract) an optional step, defaulting to 1, to a numeric property.
+    ///
+    /// Integers stay integers unless the step has a fraction.
+    fn nudge(&mut self, args: &[&str], down: bool) -> ConsoleResult {
+        let usage = if down {
+            "dec <var> [step]"
+        } else {
+            "inc <var> [step]"
+        };
+        let var = match args.get(0) {
+            Some(var) => var,
+            None => return ConsoleError::InvalidUsage(usage.to_string()).into(),
+        };
+        let step = args.get(1).unwrap_or(&"1");
+        let val = match &*self.get(var) {
+            Ok(val) => val.trim().to_string(),
+            Err(e) => return e.clone().into(),
+        };
+
+        let new = match (val.parse::<i64>(), step.parse::<i64>()) {
+            (Ok(val), Ok(step)) => if down {
+                val.checked_sub(step)
+            } else {
+                val.checked_add(step)
+            }
+            .map(|v| v.to_string()),
+            _ => match (val.parse::<f64>(), step.parse::<f64>()) {
+                (Ok(val), Ok(step)) => Some(if down { val - step } else { val + step }.to_string()),
+                (Err(_), _) => {
+                    let msg = format!("{} is {}, not a number", var, val);
+                    return ConsoleError::InvalidValue(msg).into();
+                }
+                (_, Err(_)) => {
+                    let msg = format!("step {} is not a number", step);
+                    return ConsoleError::InvalidValue(msg).into();
+                }
+            },
+        };
+        let new = match new {
+            Some(new) => new,
+            None => return ConsoleError::InvalidValue(format!("{} would overflow", var)).into(),
+        };
+        if let Err(e) = &*self.set(var, &new) {
+            return e.clone().into();
+        }
+        match &*self.get(var) {
+            Ok(val) => format!("{} is now {}", var, val).into(),
+            Err(e) => e.clone().into(),
+        }
+    }
+
+    pub fn cmd_source(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let (stop_on_error, file) = match args {
+            ["-e", file] => (true, file),
+            // a lone flag is a missing file, not a file named -e
+            [file] if *file != "-e" => (false, file),
+            _ => {
+                let usage = ConsoleError::InvalidUsage("source [-e] <file>".to_string());
+                console.write_result(usage.into());
+                return;
+            }
+        };
+        let request = WindowRequest::Source(file.to_string(), stop_on_error);
+        self.requests.push(request);
+    }
+
+    pub fn cmd_wait(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        match args.get(0).map(|frames| frames.parse::<usize>()) {
+            Some(Ok(frames)) => self.requests.push(WindowRequest::Wait(frames)),
+            _ => {
+                console.write_result(ConsoleError::InvalidUsage("wait <frames>".to_string()).into())
+            }
+        }
+    }
+
+    pub fn cmd_echo(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        // a lone word is always the text, so `echo red` still prints something
+        let color = args.get(0).filter(|_| args.len() > 1);
+        let (color, words) = match color.and_then(|arg| parse_color(arg).ok()) {
+            Some(color) => (color, &args[1..]),
+            None => ([1., 1., 1., 1.], args),
+        };
+        console.write_colored(color, &format!("{}\n", words.join(" ")));
+    }
+
+    #[cfg(feature = "persist")]
+    pub fn cmd_save(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = match args.get(0) {
+            Some(file) => {
+                let path = resolve_path(self.context.script_dir.as_deref(), file);
+                save_config(self, &path)
+            }
+            None => ConsoleError::InvalidUsage("save <file>".to_string()).into(),
+        };
+        console.write_result(out);
+    }
+
+    #[cfg(feature = "persist")]
+    pub fn cmd_load(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = match args.get(0) {
+            Some(file) => {
+                let path = resolve_path(self.context.script_dir.as_deref(), file);
+                load_with(&path, console, &mut |var, val| {
+                    self.track(var, |root| cvar::console::set(root, var, val))
+                })
+            }
+            None => ConsoleError::InvalidUsage("load <file>".to_string()).into(),
+        };
+        console.write_result(out);
+    }
+
+    pub fn cmd_uptime(&mut self, _args: &[&str], console: &mut dyn IConsoleExt) {
+        let out = match self.context.session {
+            Some(session) => format!(
+                "Up {}, {} commands run",
+                format_duration(session.started.elapsed()),
+                session.commands
+            )
+            .into(),
+            None => ConsoleError::Unimplemented.into(),
+        };
+        console.write_result(out);
+    }
+
+    pub fn cmd_time(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let (cmd, rest) = match args.split_first() {
+            Some(split) => split,
+            None => {
+                let usage = ConsoleError::InvalidUsage("time <command> [args]".to_string());
+                console.write_result(usage.into());
+                return;
+            }
+        };
+        let start = Instant::now();
+        let result = self.exec(cmd, rest.to_vec());
+        let elapsed = start.elapsed();
+
+        // builtins run this way leave their output on self.console, rather than ours
+        let inner = std::mem::replace(
+            &mut self.console,
+            ColoredConsole {
+                buf: VecDeque::new(),
+                error: None,
+                hook: None,
+            },
+        );
+        for span in inner.buf {
+            if span.role != SpanRole::Error {
+                console.write_colored(span.color, &span.text);
+            }
+        }
+        match inner.error {
+            Some(msg) => console.write_result(ConsoleError::Custom(msg.into()).into()),
+            None => console.write_result(result),
+        }
+        console.write_colored(
+            DIM_COLOR,
+            &format!("{} took {}\n", cmd, format_elapsed(elapsed)),
+        );
+    }
+
+    pub fn cmd_version(&mut self, _args: &[&str], console: &mut dyn IConsoleExt) {
+        console.write(&format!("amethyst-console {}\n", env!("CARGO_PKG_VERSION")));
+        self.requests.push(WindowRequest::About);
+    }
+
+    pub fn cmd_reset(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
         let out = {
             if let Some(var) = args.get(0) {
-                self.help(var)
+                self.reset(var)
             } else {
-                self.find(&|_| true)
+                self.reset_all()
+            }
+        };
+        console.write_result(out);
+    }
+
+    pub fn cmd_dump(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let prefix = args.get(0).cloned().unwrap_or("");
+        let mut out = vec![];
+        cvar::console::walk(self, |path, node| {
+            if !path.starts_with(prefix) || is_read_only(node.description()) {
+                return;
+            }
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                out.push(format!("{} {}", path, quote_arg(&prop.get())));
+            }
+        });
+        if out.is_empty() {
+            console.write_result(ConsoleError::NoResults.into());
+        } else {
+            console.write_result(out.join("\n").into());
+        }
+    }
+
+    pub fn cmd_setall(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        let (pattern, val) = match args {
+            [pattern, val] => (pattern, val),
+            _ => {
+                let usage = ConsoleError::InvalidUsage("setall <pattern> <value>".to_string());
+                console.write_result(usage.into());
+                return;
             }
         };
-        console.write_result(out);
-    }
 
-    pub fn cmd_find(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
-        let out = {
-            if let Some(var) = args.get(0) {
-                self.find(&|path: &str| path.contains(var) && path != "find")
+        let (mut matched, mut count) = (0, 0);
+        let mut changes = vec![];
+        cvar::console::walk(self, |path, node| {
+            let matches = if pattern.contains('*') || pattern.contains('?') {
+                glob_match(pattern, path)
             } else {
-                ConsoleError::InvalidUsage("find <name>".to_string()).into()
+                path.contains(pattern)
+            };
+            if !matches {
+                return;
             }
-        };
-        console.write_result(out);
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                matched += 1;
+                let before = prop.get();
+                match prop.set(val) {
+                    Ok(()) => {
+                        console.write(&format!("{} = {}\n", path, prop.get()));
+                        if prop.get() != before {
+                            changes.push((path.to_string(), prop.get()));
+                        }
+                        count += 1;
+                    }
+                    Err(e) => {
+                        let e = ConsoleError::InvalidValue(format!("{}: {}", path, e));
+                        console.write_result(e.into());
+                    }
+                }
+            }
+        });
+        self.changes.extend(changes);
+        if matched == 0 {
+            console.write_result(ConsoleError::NoResults.into());
+        } else {
+            console.write_result(format!("Set {} properties", count).into());
+        }
     }
 
-    pub fn cmd_reset(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
-        let out = {
-            if let Some(var) = args.get(0) {
-                self.reset(var)
-            } else {
-                self.reset_all()
+    pub fn cmd_mset(&mut self, args: &[&str], console: &mut dyn IConsoleExt) {
+        if args.is_empty() || args.len() % 2 != 0 {
+            let usage = ConsoleError::InvalidUsage("mset <var> <value> [<var> <value>...]".into());
+            console.write_result(usage.into());
+            return;
+        }
+
+        // keep going past failures, so one typo doesn't leave the rest of a preset unapplied
+        let mut errors = vec![];
+        for pair in args.chunks(2) {
+            let (var, val) = (pair[0], pair[1]);
+            match &*self.set(var, val) {
+                Ok(_) => {
+                    let val = self.get(var).0.unwrap_or_else(|_| val.to_string());
+                    console.write(&format!("{} = {}\n", var, val));
+                }
+                Err(e) => errors.push(format!("{}: {}", var, e)),
             }
+        }
+        let total = args.len() / 2;
+        let out = if errors.is_empty() {
+            format!("Set {} properties", total).into()
+        } else {
+            let msg = format!(
+                "{} of {} failed. {}",
+                errors.len(),
+                total,
+                errors.join(", ")
+            );
+            ConsoleError::InvalidValue(msg).into()
         };
         console.write_result(out);
     }
@@ -601,22 +2937,133 @@ impl<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn IConsoleExt)> cvar:
     for VisitMutExt<F>
 {
     fn visit_mut(&mut self, f: &mut dyn FnMut(&mut dyn cvar::INode)) {
-        let mut console = ColoredConsole { buf: vec![] };
+        let mut console = ColoredConsole {
+            buf: VecDeque::new(),
+            error: None,
+            hook: None,
+        };
         f(&mut cvar::Action(
             "help",
-            "List all commands and properties",
+            "[page or name]\nList all commands and properties, a page at a time. Give a name to see it, or everything in it",
             |args, _| self.cmd_help(args, &mut console),
         ));
         f(&mut cvar::Action(
             "find",
-            "<text>\nSearch for matching commands",
+            "<text>\nSearch for commands containing every word, and none starting with -. Use find -r <regex> to match a pattern instead",
             |args, _| self.cmd_find(args, &mut console),
         ));
+        f(&mut cvar::Action(
+            "info",
+            "<var>\nShow the full, untruncated value of a property",
+            |args, _| self.cmd_info(args, &mut console),
+        ));
         f(&mut cvar::Action(
             "reset",
-            "<var>\nSet a property to its default",
+            "[var]\nSet a property to its default, or every property once confirmed",
             |args, _| self.cmd_reset(args, &mut console),
         ));
+        f(&mut cvar::Action(
+            "clear",
+            "Clear the console's scrollback",
+            |_, _| self.requests.push(WindowRequest::Clear),
+        ));
+        f(&mut cvar::Action(
+            "console_reset",
+            "Return the console to a clean slate. Clears the scrollback, history, aliases, watches, bindings and queued commands, but leaves game properties alone",
+            |_, _| self.requests.push(WindowRequest::ResetState),
+        ));
+        f(&mut cvar::Action(
+            "watch",
+            "<var>\nKeep the value of a property on screen",
+            |args, _| self.cmd_watch(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "unwatch",
+            "<var>\nStop watching a property",
+            |args, _| self.cmd_unwatch(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "alias",
+            "[name] [commands]\nMake a shortcut for one or more commands. List them with no arguments",
+            |args, _| self.cmd_alias(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "unalias",
+            "<name>\nRemove a shortcut made with alias",
+            |args, _| self.cmd_unalias(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "toggle",
+            "<var>\nFlip a true/false property",
+            |args, _| self.cmd_toggle(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "inc",
+            "<var> [step]\nAdd step, or 1, to a number property",
+            |args, _| self.cmd_inc(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "dec",
+            "<var> [step]\nSubtract step, or 1, from a number property",
+            |args, _| self.cmd_dec(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "source",
+            "[-e] <file>\nRun every line of a script file as a command. Lines starting with # are skipped.\nWith -e, the script stops at the first command that fails",
+            |args, _| self.cmd_source(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "wait",
+            "<frames>\nDelay the rest of the line, and anything queued, by a number of frames (not milliseconds)",
+            |args, _| self.cmd_wait(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "echo",
+            "[color] [text]\nPrint text to the console, optionally in a color such as red or #ff8000",
+            |args, _| self.cmd_echo(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "uptime",
+            "Show how long the console has been running, and how many commands were run",
+            |args, _| self.cmd_uptime(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "time",
+            "<command> [args]\nRun a command and show how long it took",
+            |args, _| self.cmd_time(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "version",
+            "Show the console version, and the game's if it set one",
+            |args, _| self.cmd_version(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "dump",
+            "[prefix]\nPrint every property as a command that sets it, ready to paste back in or source",
+            |args, _| self.cmd_dump(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "setall",
+            "<pattern> <value>\nSet every property containing or matching (with * and ?) the pattern",
+            |args, _| self.cmd_setall(args, &mut console),
+        ));
+        f(&mut cvar::Action(
+            "mset",
+            "<var> <value> [<var> <value>...]\nSet several properties at once, carrying on past any that fail",
+            |args, _| self.cmd_mset(args, &mut console),
+        ));
+        #[cfg(feature = "persist")]
+        f(&mut cvar::Action(
+            "save",
+            "<file>\nWrite every property to a RON file",
+            |args, _| self.cmd_save(args, &mut console),
+        ));
+        #[cfg(feature = "persist")]
+        f(&mut cvar::Action(
+            "load",
+            "<file>\nSet every property listed in a RON file written by save",
+            |args, _| self.cmd_load(args, &mut console),
+        ));
         self.visit_mut_ext(f, &mut console);
         self.console = console
     }
@@ -637,6 +3084,1080 @@ pub fn VisitMutExt<F: FnMut(&mut dyn FnMut(&mut dyn cvar::INode), &mut dyn ICons
 /// Use `create_system` instead if you want amethyst integration.
 ///
 /// Be sure to call `build` on the returned window during your rendering stage
+#[cfg(feature = "window")]
 pub fn create_console() -> ConsoleWindow {
     ConsoleWindow::new()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[derive(Default)]
+    struct Config {
+        width: f32,
+        name: String,
+        paddle: Paddle,
+    }
+
+    #[derive(Default)]
+    struct Paddle {
+        velocity: f32,
+        color: String,
+    }
+
+    impl IVisitExt for Config {
+        fn visit_mut_ext(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn cvar::INode),
+            console: &mut dyn IConsoleExt,
+        ) {
+            f(&mut cvar::Property(
+                "width",
+                "Arena width",
+                &mut self.width,
+                100.,
+            ));
+            f(&mut cvar::Property(
+                "name",
+                "Player name",
+                &mut self.name,
+                String::new(),
+            ));
+            f(&mut ListExt(
+                "paddle",
+                "Paddle settings",
+                &mut self.paddle,
+                console,
+            ));
+        }
+    }
+
+    impl IVisitExt for Paddle {
+        fn visit_mut_ext(
+            &mut self,
+            f: &mut dyn FnMut(&mut dyn cvar::INode),
+            _console: &mut dyn IConsoleExt,
+        ) {
+            f(&mut cvar::Property(
+                "velocity",
+                "Speed",
+                &mut self.velocity,
+                1.,
+            ));
+            f(&mut cvar::Property(
+                "color",
+                "Color",
+                &mut self.color,
+                String::new(),
+            ));
+        }
+    }
+
+    #[test]
+    fn completion_ignores_case_but_keeps_it() {
+        let mut config = Config::default();
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console));
+        assert_eq!(root.complete("WID"), vec!["width"]);
+        assert!(root.complete("He").contains(&"help".to_string()));
+        assert!(root.complete("x").is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn common_prefix_ignores_case() {
+        let words = vec!["Paddle.Color".to_string(), "paddle.colour".to_string()];
+        assert_eq!(common_prefix(&words), "Paddle.Colo");
+        let words = vec!["width".to_string(), "name".to_string()];
+        assert_eq!(common_prefix(&words), "");
+        assert_eq!(common_prefix(&[]), "");
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn completing_the_prompt_uses_the_real_case() {
+        let mut window = ConsoleWindow::new();
+        let mut config = Config::default();
+        window.set_prompt_text("WIDT");
+        window.complete_prompt(&mut config);
+        assert_eq!(window.prompt.to_str(), "width ");
+        window.set_prompt_text("NAME");
+        window.complete_prompt(&mut config);
+        assert_eq!(window.prompt.to_str(), "name ");
+    }
+
+    #[test]
+    fn tokenize_splits_on_whitespace() {
+        assert_eq!(tokenize("  set   width 10 "), vec!["set", "width", "10"]);
+        assert!(tokenize("").is_empty());
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_words_together() {
+        assert_eq!(tokenize(r#"name "Big Bob""#), vec!["name", "Big Bob"]);
+        assert_eq!(tokenize("name 'it''s'"), vec!["name", "its"]);
+        assert_eq!(tokenize(r#"name "open"#), vec!["name", "open"]);
+        assert_eq!(tokenize(r#"echo """#), vec!["echo", ""]);
+    }
+
+    #[test]
+    fn tokenize_escapes() {
+        assert_eq!(tokenize(r#"echo a\ b \"c"#), vec!["echo", "a b", "\"c"]);
+        assert_eq!(tokenize(r"echo end\"), vec!["echo", "end\\"]);
+    }
+
+    #[test]
+    fn quoted_arguments_reach_properties_whole() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        assert!(console
+            .execute(&mut config, "set name \"Big  Bob\"")
+            .is_ok());
+        assert_eq!(config.name, "Big  Bob");
+        assert!(console.execute(&mut config, r"name it\'s").is_ok());
+        assert_eq!(config.name, "it's");
+    }
+
+    #[test]
+    fn commands_split_on_semicolons_outside_quotes() {
+        assert_eq!(
+            split_commands("width 5; name x"),
+            vec!["width 5", " name x"]
+        );
+        assert_eq!(
+            split_commands(r#"echo "a; b"; echo 'c;d'"#),
+            vec![r#"echo "a; b""#, " echo 'c;d'"]
+        );
+        assert_eq!(split_commands(r"echo a\; b"), vec![r"echo a\; b"]);
+        assert_eq!(split_commands("echo \"a; b"), vec!["echo \"a; b"]);
+        assert_eq!(split_commands(";; width 5 ;  ;"), vec![" width 5 "]);
+        assert!(split_commands("").is_empty());
+    }
+
+    #[test]
+    fn markup_colors_nest() {
+        let (red, blue) = ([1., 0., 0., 1.], [0., 0., 1., 1.]);
+        let plain = TextSpan::default().color;
+        let spans = TextSpan::parse_markup("[red]a[#0000ff]b[/]c[/]d");
+        let parsed = spans.iter().map(|s| (s.text.as_str(), s.color));
+        assert_eq!(
+            parsed.collect::<Vec<_>>(),
+            vec![("a", red), ("b", blue), ("c", red), ("d", plain)]
+        );
+    }
+
+    #[test]
+    fn markup_keeps_unknown_and_unterminated_tags() {
+        let text = |markup| {
+            let spans = TextSpan::parse_markup(markup);
+            spans.into_iter().map(|s| s.text).collect::<Vec<_>>()
+        };
+        assert_eq!(text("a [/] b"), vec!["a [/] b"]);
+        assert_eq!(text("[nope]x [red"), vec!["[nope]x [red"]);
+        assert_eq!(text("[red]open"), vec!["open"]);
+        assert_eq!(
+            TextSpan::parse_markup("[red]open")[0].color,
+            [1., 0., 0., 1.]
+        );
+        assert!(text("[red][/]").is_empty());
+    }
+
+    #[test]
+    fn redirects_split_at_the_first_unquoted_arrow() {
+        assert_eq!(
+            split_redirect("find width > out.txt"),
+            Some(("find width ", "out.txt".to_string(), false))
+        );
+        assert_eq!(
+            split_redirect("find width >> \"my log.txt\""),
+            Some(("find width ", "my log.txt".to_string(), true))
+        );
+        assert_eq!(
+            split_redirect("echo \"a > b\" > out"),
+            Some(("echo \"a > b\" ", "out".to_string(), false))
+        );
+        assert_eq!(split_redirect(r"echo a \> b"), None);
+        assert_eq!(split_redirect("echo 'a > b'"), None);
+    }
+
+    #[test]
+    fn colors_parse_from_names_and_hex() {
+        for (name, color) in NAMED_COLORS {
+            assert_eq!(parse_color(name).unwrap(), *color);
+        }
+        assert_eq!(parse_color(" Orange ").unwrap(), [1., 0.5, 0., 1.]);
+        assert_eq!(parse_color("#00ff00").unwrap(), [0., 1., 0., 1.]);
+        assert_eq!(parse_color("#ffffff00").unwrap(), [1., 1., 1., 0.]);
+        match parse_color("#fff") {
+            Err(ConsoleError::InvalidValue(msg)) => assert!(msg.contains("one of: white")),
+            other => panic!("expected an invalid value, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn color_properties_take_names_and_show_hex() {
+        let mut color = [1.; 4];
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut ColorProperty("tint", "Tint", &mut color, [1.; 4]));
+        });
+        assert!(root.set("tint", "red").is_ok());
+        assert_eq!(root.get("tint").0.unwrap(), "#ff0000");
+        assert!(root.set("tint", "#0000ff80").is_ok());
+        assert_eq!(root.get("tint").0.unwrap(), "#0000ff80");
+        assert!(root.set("tint", "mauve").is_err());
+        assert_eq!(root.get("tint").0.unwrap(), "#0000ff80");
+        assert!(root.reset("tint").is_ok());
+        drop(root);
+        assert_eq!(color, [1.; 4]);
+    }
+
+    #[test]
+    fn enum_properties_only_take_their_variants() {
+        let mut mode = "windowed".to_string();
+        let modes = ["windowed", "fullscreen"];
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut EnumProperty(
+                "mode",
+                "Display mode",
+                &mut mode,
+                "windowed",
+                &modes,
+            ));
+        });
+        assert!(root.set("mode", "FullScreen").is_ok());
+        assert_eq!(root.get("mode").0.unwrap(), "fullscreen");
+        let err = root.set("mode", "borderless").0.unwrap_err().to_string();
+        assert!(
+            err.contains("expected one of windowed, fullscreen"),
+            "{}",
+            err
+        );
+        assert_eq!(root.get("mode").0.unwrap(), "fullscreen");
+        assert!(root.reset("mode").is_ok());
+        drop(root);
+        assert_eq!(mode, "windowed");
+    }
+
+    #[test]
+    fn clamped_properties_clamp_unless_strict() {
+        let (mut volume, mut lives) = (0.5f32, 3i32);
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut ClampedProperty(
+                "volume",
+                "Volume",
+                &mut volume,
+                0.5,
+                0.,
+                1.,
+            ));
+            f(&mut ClampedProperty("lives", "Lives", &mut lives, 3, 1, 9).strict());
+        });
+        assert!(root.set("volume", "0.25").is_ok());
+        assert_eq!(root.get("volume").0.unwrap(), "0.25");
+        assert!(root.set("volume", "7").is_ok());
+        assert_eq!(root.get("volume").0.unwrap(), "1");
+        assert!(root.set("volume", "-2").is_ok());
+        assert_eq!(root.get("volume").0.unwrap(), "0");
+        assert!(root.set("volume", "loud").is_err());
+
+        assert!(root.set("lives", "9").is_ok());
+        let err = root.set("lives", "10").0.unwrap_err().to_string();
+        assert!(err.contains("10 is outside 1 to 9"), "{}", err);
+        assert_eq!(root.get("lives").0.unwrap(), "9");
+        drop(root);
+        assert_eq!((volume, lives), (0., 9));
+    }
+
+    #[test]
+    fn vec_properties_set_every_value_at_once() {
+        let (mut pos, mut cell) = ([0f32; 3], [0i32; 2]);
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut VecProperty(
+                "pos",
+                "Spawn position",
+                &mut pos,
+                &[0.; 3],
+            ));
+            f(&mut VecProperty("cell", "Grid cell", &mut cell, &[0, 0]));
+        });
+        assert!(root.set("pos", "1 2.5 3").is_ok());
+        assert_eq!(root.get("pos").0.unwrap(), "1 2.5 3");
+        assert!(root.set("pos", "4, 5,6").is_ok());
+        assert_eq!(root.get("pos").0.unwrap(), "4 5 6");
+
+        let err = root.set("pos", "1 2").0.unwrap_err().to_string();
+        assert!(err.contains("Expected 3 values, got 2"), "{}", err);
+        assert!(root.set("pos", "7 x 9").is_err());
+        assert_eq!(root.get("pos").0.unwrap(), "4 5 6");
+        assert!(root.reset("pos").is_ok());
+
+        assert!(root.set("cell", "-1 8").is_ok());
+        assert!(root.set("cell", "1.5 2").is_err());
+        assert_eq!(root.get("cell").0.unwrap(), "-1 8");
+        drop(root);
+        assert_eq!((pos, cell), ([0.; 3], [-1, 8]));
+    }
+
+    #[test]
+    fn read_only_properties_reject_set() {
+        let fps = 60;
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut ReadOnlyProperty("fps", "Frames per second", &fps));
+        });
+        assert_eq!(root.get("fps").0.unwrap(), "60");
+        let err = root.set("fps", "30").0.unwrap_err().to_string();
+        assert!(err.contains("read-only"), "{}", err);
+        assert!(root.reset("fps").is_ok());
+        assert_eq!(root.get("fps").0.unwrap(), "60");
+
+        let mut console = HeadlessConsole::new();
+        assert!(console.execute(&mut root, "fps 30").is_err());
+        console.execute(&mut root, "help fps");
+        let help = console.take_text();
+        assert!(help.contains("(read-only)"), "{}", help);
+    }
+
+    #[test]
+    fn setall_sets_every_matching_property() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "setall paddle.* 3").is_ok());
+        assert!(console.take_text().ends_with("Set 2 properties\n"));
+        assert_eq!(config.paddle.velocity, 3.);
+        assert_eq!(config.paddle.color, "3");
+        assert!(console.execute(&mut config, "setall idt 7").is_ok());
+        assert_eq!(config.width, 7.);
+
+        assert!(console
+            .execute(&mut config, "setall paddle.* fast")
+            .is_err());
+        assert_eq!(
+            (config.paddle.velocity, config.paddle.color.as_str()),
+            (3., "fast")
+        );
+        assert!(console.execute(&mut config, "setall nothing* 1").is_err());
+        assert!(console.take_text().ends_with("No results\n"));
+    }
+
+    #[test]
+    fn mset_sets_each_pair_past_failures() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        assert!(console
+            .execute(&mut config, "mset width 5 name \"Big Bob\"")
+            .is_ok());
+        assert_eq!((config.width, config.name.as_str()), (5., "Big Bob"));
+        assert!(console.take_text().ends_with("Set 2 properties\n"));
+
+        assert!(console
+            .execute(&mut config, "mset width wide paddle.velocity 2")
+            .is_err());
+        assert_eq!((config.width, config.paddle.velocity), (5., 2.));
+        assert!(console.take_text().contains("1 of 2 failed. width:"));
+        assert!(console.execute(&mut config, "mset width").is_err());
+    }
+
+    #[test]
+    fn toggle_flips_only_booleans() {
+        let mut console = HeadlessConsole::new();
+        let mut vsync = false;
+        let mut config = Config::default();
+        let mut root = VisitMutExt(|f, console| {
+            f(&mut cvar::Property(
+                "vsync",
+                "Wait for vsync",
+                &mut vsync,
+                false,
+            ));
+            config.visit_mut_ext(f, console);
+        });
+        assert!(console.execute(&mut root, "toggle vsync").is_ok());
+        assert_eq!(console.take_text(), "vsync is now true\n");
+        assert!(console.execute(&mut root, "toggle vsync").is_ok());
+
+        assert!(console
+            .execute(&mut root, "width 12; toggle width")
+            .is_err());
+        assert!(console
+            .take_text()
+            .contains("width is 12, not true or false"));
+        assert!(console.execute(&mut root, "toggle nope").is_err());
+        assert!(console.execute(&mut root, "toggle").is_err());
+        drop(root);
+        assert_eq!((vsync, config.width), (false, 12.));
+    }
+
+    #[test]
+    fn inc_and_dec_nudge_numbers() {
+        let mut console = HeadlessConsole::new();
+        let mut count = 5i32;
+        let mut config = Config::default();
+        let mut root = VisitMutExt(|f, console| {
+            f(&mut cvar::Property("count", "Enemy count", &mut count, 5));
+            config.visit_mut_ext(f, console);
+        });
+        assert!(console.execute(&mut root, "inc count; dec count 3").is_ok());
+        assert!(console.execute(&mut root, "width 1; inc width 0.5").is_ok());
+        assert!(console.execute(&mut root, "dec count 0.5").is_err());
+
+        assert!(console.execute(&mut root, "name bob; inc name").is_err());
+        assert!(console.take_text().contains("name is bob, not a number"));
+        assert!(console.execute(&mut root, "inc count lots").is_err());
+        assert!(console.execute(&mut root, "dec").is_err());
+        drop(root);
+        assert_eq!((count, config.width), (3, 1.5));
+    }
+
+    /// Counts the records a chained `ConsoleLogger` passes on.
+    struct CountingLogger(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+    impl log::Log for CountingLogger {
+        fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+            true
+        }
+        fn log(&self, _record: &log::Record<'_>) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn logger_shows_its_level_and_passes_everything_on() {
+        use log::{Level, Log};
+        let passed = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let logger = ConsoleLogger::new()
+            .level(log::LevelFilter::Warn)
+            .chain(Box::new(CountingLogger(passed.clone())));
+        let mut console = HeadlessConsole::new();
+        console.console().attach_logger(&logger);
+        for &level in &[Level::Error, Level::Warn, Level::Info] {
+            // built and logged in one go, as the arguments only live for the statement
+            logger.log(
+                &log::Record::builder()
+                    .level(level)
+                    .args(format_args!("fuel low"))
+                    .build(),
+            );
+        }
+        console.console().run_queued(&mut Config::default());
+        assert_eq!(console.take_text(), "[ERROR] fuel low\n[WARN] fuel low\n");
+        assert_eq!(passed.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let debug = log::Metadata::builder().level(Level::Debug).build();
+        assert!(logger.enabled(&debug));
+        assert!(!ConsoleLogger::new().enabled(&debug));
+    }
+
+    #[test]
+    fn sinks_are_drained_from_other_threads() {
+        let mut console = HeadlessConsole::new();
+        let sink = console.console().sink();
+        let writers = (0..4)
+            .map(|n| {
+                let sink = sink.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        sink.writeln(format!("thread {} line {}", n, i));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        console.console().run_queued(&mut Config::default());
+        let text = console.take_text();
+        assert_eq!(text.lines().count(), 100);
+        for n in 0..4 {
+            let lines = text
+                .lines()
+                .filter(|l| l.starts_with(&format!("thread {} ", n)));
+            let expected = (0..25).map(|i| format!("thread {} line {}", n, i));
+            assert!(lines.eq(expected), "thread {} out of order", n);
+        }
+        console.console().run_queued(&mut Config::default());
+        assert_eq!(console.take_text(), "");
+    }
+
+    /// An empty directory of its own for each test that writes files.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("console-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn history_saves_and_loads_within_the_limit() {
+        let dir = temp_dir("history");
+        let path = dir.join("history.txt");
+        let mut console = Console::new();
+        for cmd in &["width 1", "  ", "width 2", "name bob"] {
+            console.push_history(cmd);
+        }
+        assert!(console.save_history(&path).is_ok());
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(saved, "width 1\nwidth 2\nname bob\n");
+
+        // loaded commands go in front of what was typed since, and the oldest are dropped
+        let mut console = Console::new();
+        console.set_history_limit(3);
+        console.push_history("echo new");
+        assert!(console.load_history(&path).is_ok());
+        assert_eq!(console.history, vec!["width 2", "name bob", "echo new"]);
+        assert!(console.load_history(&dir.join("missing.txt")).is_err());
+    }
+
+    #[test]
+    fn redirected_values_are_not_formatted() {
+        let dir = temp_dir("redirect-format");
+        let mut console = HeadlessConsole::new();
+        console.console().set_number_grouping(true);
+        console.console().set_value_display_width(3);
+        console.console().set_script_dir(&dir);
+        let mut config = Config::default();
+        config.width = 1234567.;
+        console.execute(&mut config, "width > width.txt");
+        let text = std::fs::read_to_string(dir.join("width.txt")).unwrap();
+        assert_eq!(text, "1234567\n");
+        console.take_text();
+        console.execute(&mut config, "width");
+        assert_eq!(console.take_text(), "1,2... (9 chars)\n");
+    }
+
+    #[test]
+    fn substituted_values_stay_one_argument() {
+        let mut config = Config::default();
+        config.name = "a \"b".to_string();
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console));
+        let line = substitute(&mut root, "echo $(name) $(width)").unwrap();
+        assert_eq!(tokenize(&line), vec!["echo", "a \"b", "0"]);
+        let line = substitute(&mut root, "echo \"hi $(name)!\" 'x$(name)'").unwrap();
+        assert_eq!(tokenize(&line), vec!["echo", "hi a \"b!", "xa \"b"]);
+    }
+
+    #[test]
+    fn console_reset_clears_console_state() {
+        let mut console = Console::new();
+        let mut config = Config::default();
+        console.history.push("width 5".to_string());
+        console.execute(&mut config, "alias wide width 200");
+        console.execute(&mut config, "watch width");
+        console.queue_command("width 7".to_string());
+        console.execute(&mut config, "width 3; console_reset");
+        assert!(console.history.is_empty());
+        assert!(console.aliases.is_empty());
+        assert!(console.watches.is_empty());
+        assert!(console.queue.is_empty());
+        assert!(console.take_state_reset());
+        assert!(!console.take_state_reset());
+        assert_eq!(config.width, 3.);
+    }
+
+    #[test]
+    fn console_reset_can_keep_history() {
+        let mut console = Console::new();
+        console.set_reset_clears_history(false);
+        let mut config = Config::default();
+        console.history.push("width 5".to_string());
+        console.execute(&mut config, "console_reset");
+        assert_eq!(console.history, vec!["width 5"]);
+    }
+
+    #[test]
+    fn fuzzy_score_matches_subsequences() {
+        assert_eq!(fuzzy_score("pvel", "paddle.velocity"), Some(26));
+        assert_eq!(fuzzy_score("PVEL", "paddle.velocity"), Some(26));
+        assert_eq!(fuzzy_score("pvel", "pvel"), Some(34));
+        assert_eq!(fuzzy_score("", "width"), Some(0));
+        assert_eq!(fuzzy_score("pvel", "paddle.color"), None);
+        assert_eq!(fuzzy_score("lev", "level"), Some(25));
+    }
+
+    #[test]
+    fn fuzzy_completion_ranks_best_first() {
+        let mut config = Config::default();
+        let mut root = VisitMutExt(|f, console| config.visit_mut_ext(f, console));
+        assert_eq!(
+            root.complete_fuzzy("pc"),
+            vec!["paddle.color", "paddle.velocity"]
+        );
+        assert_eq!(root.complete_fuzzy("pvel"), vec!["paddle.velocity"]);
+    }
+
+    #[test]
+    fn fuzzy_find_is_opt_in() {
+        let mut config = Config::default();
+        let mut console = HeadlessConsole::new();
+        assert!(console.execute(&mut config, "find pc").is_err());
+
+        let mut console = HeadlessConsole::new();
+        console.console().set_fuzzy_matching(true);
+        assert!(console.execute(&mut config, "find pc").is_ok());
+        let text = console.take_text();
+        let color = text.find("paddle.color").unwrap();
+        let velocity = text.find("paddle.velocity").unwrap();
+        assert!(color < velocity);
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn fuzzy_completion_extends_as_far_as_candidates_agree() {
+        let mut config = Config::default();
+        let mut window = ConsoleWindow::builder().fuzzy_matching(true).build();
+        window.set_prompt_text("pc");
+        window.complete_prompt(&mut config);
+        assert_eq!(window.prompt.to_str(), "paddle.");
+        window.complete_prompt(&mut config);
+        assert_eq!(window.prompt.to_str(), "paddle.color");
+
+        // the candidates agree on less than was typed, which is left alone
+        window.set_prompt_text("dt");
+        window.complete_prompt(&mut config);
+        assert_eq!(window.prompt.to_str(), "dt");
+    }
+
+    #[test]
+    fn self_sourcing_script_stops() {
+        let dir = temp_dir("self-source");
+        std::fs::write(dir.join("loop.txt"), "width 5\nsource loop.txt\n").unwrap();
+        let mut console = HeadlessConsole::new();
+        console.console().set_script_dir(&dir);
+        let mut config = Config::default();
+        console.execute(&mut config, "source loop.txt");
+        assert_eq!(config.width, 5.);
+        assert!(console
+            .take_text()
+            .contains("scripts nested too deeply in loop.txt"));
+        assert!(console.console().queue.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn window_follows_its_console() {
+        let mut window = ConsoleWindow::new();
+        let mut config = Config::default();
+        window.set_prompt_text("width");
+        window.history_pos = Some(0);
+        window.execute(&mut config, "console_reset");
+        window.sync();
+        assert_eq!(window.prompt.to_str(), "");
+        assert_eq!(window.history_pos, None);
+
+        window.set_scrollback_limit(1);
+        window.line_heights = vec![Some(1.); 2];
+        window.selected.insert(1);
+        window.execute(&mut config, "echo one; echo two; echo three");
+        window.sync();
+        assert_eq!(window.line_heights.len(), 0);
+        assert!(window.selected.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn window_line_index_follows_the_scrollback() {
+        let mut console = Console::new();
+        let mut index = crate::window::LineIndex::default();
+        let check = |index: &mut crate::window::LineIndex, console: &mut Console, filter| {
+            let buf = console.output.buf.make_contiguous();
+            index.update(buf, console.dropped_spans, filter);
+            let lines = (0..index.len()).map(|n| index.line(n)).collect::<Vec<_>>();
+            assert_eq!(lines, line_ranges(&*buf));
+            (0..index.shown_len())
+                .map(|i| index.shown(i))
+                .collect::<Vec<_>>()
+        };
+
+        console.output.write("open ");
+        assert_eq!(check(&mut index, &mut console, ""), vec![0]);
+        console.output.writeln("line");
+        console.output.writeln("Two");
+        assert_eq!(check(&mut index, &mut console, "two"), vec![1]);
+        console.output.writeln("three");
+        console.output.writeln("two again");
+        // drops the first half of the first line
+        console.set_scrollback_limit(4);
+        assert_eq!(check(&mut index, &mut console, "two"), vec![1, 3]);
+        assert_eq!(check(&mut index, &mut console, ""), vec![0, 1, 2, 3]);
+        console.clear();
+        assert!(check(&mut index, &mut console, "").is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn watches_are_shown_with_the_value_format() {
+        let mut console = Console::new();
+        console.set_number_grouping(true);
+        let mut watch = Watch {
+            path: "width".into(),
+            value: Some("1000000".into()),
+            changed: None,
+        };
+        let label = crate::window::watch_label(&watch, &console.value_format);
+        assert_eq!(label, "width: 1,000,000");
+        assert_eq!(watch.value.as_deref(), Some("1000000"));
+        watch.value = None;
+        assert_eq!(
+            crate::window::watch_label(&watch, &console.value_format),
+            "width: ?"
+        );
+    }
+
+    /// Send `input` to `remote` and close, returning everything sent back.
+    #[cfg(feature = "remote")]
+    fn remote_session(remote: &mut RemoteConsole, config: &mut Config, input: &[u8]) -> String {
+        use std::io::{Read, Write};
+        let mut stream = std::net::TcpStream::connect(remote.local_addr().unwrap()).unwrap();
+        stream.write_all(input).unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        for _ in 0..50 {
+            remote.poll(config);
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        let mut text = String::new();
+        stream.read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn remote_runs_lines_sent_before_closing() {
+        let mut remote = RemoteConsole::localhost(0).unwrap();
+        let mut config = Config::default();
+        let text = remote_session(&mut remote, &mut config, b"width\n\nname dave\nname");
+        assert_eq!(text, "0\n\ndave\n");
+        let changes = remote.take_changes();
+        assert_eq!(changes, vec![("name".to_string(), "dave".to_string())]);
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn remote_lines_run_like_the_console() {
+        let mut remote = RemoteConsole::localhost(0).unwrap();
+        let mut config = Config::default();
+        let input = b"alias big width 500; big\nwidth\nwatch width\nreset\n";
+        let text = remote_session(&mut remote, &mut config, input);
+        assert!(text.starts_with("\n500\n"), "{}", text);
+        assert!(text.contains("watches are only shown in the console window"));
+        assert!(text.contains("can't be undone"));
+        assert_eq!(config.width, 500.);
+        let executed = remote.take_executed();
+        assert_eq!(executed.len(), 4);
+        assert!(!executed[2].1);
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn remote_needs_a_password_past_loopback() {
+        let err = RemoteConsole::bind("0.0.0.0:0").err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(RemoteConsole::bind_with_password("0.0.0.0:0", "hunter2").is_ok());
+    }
+
+    #[test]
+    #[cfg(feature = "remote")]
+    fn remote_drops_overlong_lines() {
+        let mut remote = RemoteConsole::localhost(0).unwrap();
+        let mut config = Config::default();
+        let mut input = b"name ".to_vec();
+        input.extend(std::iter::repeat(b'a').take(5000));
+        let text = remote_session(&mut remote, &mut config, &input);
+        assert_eq!(text, "Line too long\n");
+        assert_eq!(config.name, "");
+    }
+
+    #[test]
+    #[cfg(feature = "amethyst-system")]
+    fn bindings_wait_while_typing() {
+        use ::amethyst::input::{InputEvent, VirtualKeyCode};
+        let mut system =
+            ConsoleSystem::<()>::new(ConsoleWindow::new()).with_binding("F5", "width 1");
+        let press = InputEvent::KeyPressed {
+            key_code: VirtualKeyCode::F5,
+            scancode: 0,
+        };
+        assert_eq!(system.bound_command(&press), Some("width 1".to_string()));
+        system.console().prompt_active = true;
+        assert_eq!(system.bound_command(&press), None);
+        let action = InputEvent::ActionPressed("F5".to_string());
+        assert_eq!(system.bound_command(&action), None);
+    }
+
+    #[test]
+    fn empty_errors_still_show_a_red_line() {
+        use cvar::IConsole;
+        let mut out = ColoredConsole {
+            buf: VecDeque::new(),
+            error: None,
+            hook: None,
+        };
+        out.write_error(&ConsoleError::Custom(TextSpan::default()));
+        assert_eq!(out.buf.len(), 1);
+        assert_eq!(out.buf[0].text, "Command failed\n");
+        assert_eq!(out.buf[0].color, [1., 0., 0., 1.]);
+        assert_eq!(out.buf[0].role, SpanRole::Error);
+        assert_eq!(out.error, Some("Command failed".to_string()));
+    }
+
+    #[test]
+    fn redirects_leave_errors_out_of_the_file() {
+        let dir = temp_dir("redirect-errors");
+        let mut console = HeadlessConsole::new();
+        console.console().set_script_dir(&dir);
+        console
+            .console()
+            .add_command("mixed", "\nWrites output and an error", |_, out| {
+                out.write("made\n");
+                out.write_error(&ConsoleError::InvalidValue("bad".to_string()));
+            });
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "mixed > mixed.txt").is_err());
+        let text = std::fs::read_to_string(dir.join("mixed.txt")).unwrap();
+        assert_eq!(text, "made\n");
+        assert_eq!(console.take_text(), "Invalid value: bad\n");
+    }
+
+    #[test]
+    fn scripts_skip_blank_lines_and_comments() {
+        let dir = temp_dir("script-comments");
+        std::fs::write(dir.join("setup.txt"), "# wide\n\n  width 3  \n#width 4\n").unwrap();
+        let mut console = HeadlessConsole::new();
+        console.console().set_script_dir(&dir);
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "source setup.txt").is_ok());
+        assert_eq!(config.width, 3.);
+        assert_eq!(console.take_text(), " > width 3\n");
+    }
+
+    #[test]
+    fn span_text_includes_collapsed_detail() {
+        let span = TextSpan {
+            text: "summary\n".to_string(),
+            detail: vec!["one\n".into(), "two\n".into()],
+            ..Default::default()
+        };
+        let mut text = String::new();
+        span_text(&span, &mut text);
+        assert_eq!(text, "summary\none\ntwo\n");
+    }
+
+    #[test]
+    fn spans_are_built_with_methods() {
+        let span = TextSpan::from("spawn\n")
+            .with_command("spawn 10")
+            .with_style(SpanStyle::Underline)
+            .with_role(SpanRole::Error)
+            .with_background([0., 0., 0., 1.])
+            .with_detail(vec!["hidden\n".into()]);
+        assert_eq!(span.text, "spawn\n");
+        assert_eq!(span.command(), Some("spawn 10"));
+        assert_eq!(span.style(), SpanStyle::Underline);
+        assert_eq!(span.role(), SpanRole::Error);
+        assert_eq!(span.background(), Some([0., 0., 0., 1.]));
+        assert_eq!(span.detail().len(), 1);
+        assert_eq!(span.time(), None);
+    }
+
+    #[test]
+    fn scrollback_drops_the_oldest_spans() {
+        let mut console = Console::new();
+        console.set_scrollback_limit(2);
+        let mut config = Config::default();
+        console.execute(&mut config, "echo one; echo two; echo three; echo four");
+        let kept = console.output.buf.iter().map(|span| span.text.as_str());
+        assert_eq!(kept.collect::<Vec<_>>(), vec!["three\n", "four\n"]);
+        assert_eq!(console.dropped_lines, 2);
+        assert_eq!(line_ranges(&console.output.buf), vec![0..1, 1..2]);
+    }
+
+    #[test]
+    fn only_set_values_are_reported_as_changes() {
+        let mut console = HeadlessConsole::new();
+        console.console().set_record_changes(true);
+        let mut config = Config::default();
+        console.execute(&mut config, "width 5; width 5; name; paddle.velocity 2");
+        console.execute(&mut config, "reset width");
+        let changes = console.console().take_changes();
+        let expected = [("width", "5"), ("paddle.velocity", "2"), ("width", "100")];
+        let expected = expected
+            .iter()
+            .map(|(path, value)| (path.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "window")]
+    fn statusbar_key_expands_into_the_window() {
+        let mut window = ConsoleWindow::new();
+        assert!(!window.is_statusbar_expanded());
+        window.toggle_statusbar();
+        assert!(window.is_statusbar_expanded());
+        assert!(window.focus_prompt);
+        window.toggle_statusbar();
+        assert!(!window.is_statusbar_expanded());
+    }
+
+    #[test]
+    #[cfg(feature = "testing")]
+    fn test_console_runs_lines_like_the_console() {
+        let mut console = TestConsole::new(Config::default());
+        console.console().alias("big", "width 500");
+        assert_eq!(console.run_text("big; width"), "500\n");
+        assert!(console.run_text("reset").contains("can't be undone"));
+        assert_eq!(console.root().width, 500.);
+
+        let mut console = TestConsole::new(VisitMutExt(|f, console| {
+            f(&mut cvar::Action(
+                "fold",
+                "Write a collapsed line",
+                |_, _| console.write_collapsible("summary\n", vec!["one\n".into()]),
+            ));
+        }));
+        assert_eq!(console.run_text("fold"), "summary\none\n");
+    }
+
+    #[test]
+    fn aliases_expand_with_their_arguments() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        console.execute(&mut config, "alias w width; alias pair \"width 3; name x\"");
+        assert!(console.execute(&mut config, "w 7").is_ok());
+        assert_eq!(config.width, 7.);
+        assert!(console.execute(&mut config, "pair").is_ok());
+        assert_eq!((config.width, config.name.as_str()), (3., "x"));
+    }
+
+    #[test]
+    fn aliases_keep_quoted_arguments() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        console.execute(&mut config, "alias bob name \"Big Bob\"");
+        assert!(console.execute(&mut config, "bob").is_ok());
+        assert_eq!(config.name, "Big Bob");
+    }
+
+    #[test]
+    fn unalias_removes_only_known_aliases() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        console.execute(&mut config, "alias w width 5");
+        assert!(console.execute(&mut config, "unalias w").is_ok());
+        assert!(console.execute(&mut config, "w").is_err());
+        assert!(console.execute(&mut config, "unalias w").is_err());
+        assert!(console.take_text().contains("no alias named w"));
+    }
+
+    #[test]
+    fn recursive_aliases_stop() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        console.execute(&mut config, "alias a b; alias b a");
+        assert!(console.execute(&mut config, "a").is_err());
+        assert!(console
+            .take_text()
+            .contains("aliases nested too deeply in a"));
+    }
+
+    #[test]
+    #[cfg(feature = "persist")]
+    fn saved_config_loads_back_from_the_script_dir() {
+        let dir = temp_dir("persist");
+        let mut console = HeadlessConsole::new();
+        console.console().set_script_dir(&dir);
+        let mut config = Config::default();
+        console.execute(&mut config, "width 5; name \"Big Bob\"; save cfg.ron");
+        assert!(dir.join("cfg.ron").exists());
+
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "load cfg.ron").is_ok());
+        assert_eq!((config.width, config.name.as_str()), (5., "Big Bob"));
+    }
+
+    #[test]
+    #[cfg(feature = "persist")]
+    fn load_skips_read_only_and_unknown_properties() {
+        let dir = temp_dir("persist-skip");
+        let path = dir.join("cfg.ron");
+        let mut width = 1.;
+        let build = 3;
+        let mut root = VisitMutExt(|f, _| {
+            f(&mut cvar::Property("width", "Arena width", &mut width, 1.));
+            f(&mut ReadOnlyProperty("build", "Build number", &build));
+        });
+        assert!(save_config(&mut root, &path).is_ok());
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("width") && !saved.contains("build"));
+
+        let text = "{\"width\": \"4\", \"build\": \"9\", \"gone\": \"1\"}";
+        std::fs::write(&path, text).unwrap();
+        let mut out = ColoredConsole {
+            buf: VecDeque::new(),
+            error: None,
+            hook: None,
+        };
+        let result = load_config(&mut root, &path, &mut out);
+        assert_eq!(
+            result.0.unwrap(),
+            format!("Loaded 1 of 3 properties from {}", path.display())
+        );
+        let written = spans_text(&out.buf);
+        assert!(written.contains("Skipped unknown property gone"));
+        assert!(written.contains("for build"));
+        drop(root);
+        assert_eq!(width, 4.);
+    }
+
+    #[test]
+    fn source_flag_without_a_file_is_usage() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "source -e").is_err());
+        assert!(console.take_text().contains("source [-e] <file>"));
+    }
+
+    #[test]
+    fn alias_without_arguments_lists_aliases() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "alias").is_ok());
+        console.execute(&mut config, "alias wide width 200");
+        assert!(console.execute(&mut config, "alias").is_ok());
+        assert!(console.take_text().contains("wide"));
+    }
+
+    #[test]
+    fn abbreviated_reset_is_confirmed() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        config.width = 5.;
+        console.execute(&mut config, "res");
+        assert_eq!(config.width, 5.);
+        console.execute(&mut config, "res");
+        assert_eq!(config.width, 100.);
+    }
+
+    #[test]
+    fn timed_reset_is_confirmed() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        config.width = 5.;
+        console.execute(&mut config, "time reset");
+        assert_eq!(config.width, 5.);
+        console.execute(&mut config, "time rese --yes");
+        assert_eq!(config.width, 100.);
+    }
+
+    #[test]
+    fn echo_without_arguments_is_harmless() {
+        let mut console = HeadlessConsole::new();
+        let mut config = Config::default();
+        assert!(console.execute(&mut config, "echo").is_ok());
+        assert!(console.execute(&mut config, "echo red").is_ok());
+        assert_eq!(console.take_text(), "\nred\n");
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{Console, ConsoleSink, TextSpan};
+use std::time::Instant;
+
+/// `log::Log` implementation which shows log records in a console window.
+///
+/// Records can come from any thread. They are queued, and added to the window the next time it
+/// is built. Attach it to a console or window with `attach_logger`, then install it with
+/// `init`. Use `chain` to keep an existing logger, such as one writing to stdout, working too.
+pub struct ConsoleLogger {
+    sink: ConsoleSink,
+    level: log::LevelFilter,
+    next: Option<Box<dyn log::Log>>,
+}
+
+impl ConsoleLogger {
+    /// A logger showing `Info` and more severe records.
+    pub fn new() -> ConsoleLogger {
+        ConsoleLogger::with_sink(ConsoleSink::default())
+    }
+
+    /// A logger writing straight to a window's sink, so it doesn't need to be attached.
+    pub fn with_sink(sink: ConsoleSink) -> ConsoleLogger {
+        ConsoleLogger {
+            sink,
+            level: log::LevelFilter::Info,
+            next: None,
+        }
+    }
+
+    /// Show records of `level` and more severe in the console.
+    pub fn level(mut self, level: log::LevelFilter) -> ConsoleLogger {
+        self.level = level;
+        self
+    }
+
+    /// Pass every record on to `next` as well.
+    pub fn chain(mut self, next: Box<dyn log::Log>) -> ConsoleLogger {
+        self.next = Some(next);
+        self
+    }
+
+    /// Install this as the global logger.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        let level = std::cmp::max(self.level, log::max_level());
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Default for ConsoleLogger {
+    fn default() -> ConsoleLogger {
+        ConsoleLogger::new()
+    }
+}
+
+/// Text color for a record, from red errors down to grey debug output.
+fn level_color(level: log::Level) -> [f32; 4] {
+    match level {
+        log::Level::Error => [1., 0., 0., 1.],
+        log::Level::Warn => [1., 1., 0., 1.],
+        log::Level::Info => [1., 1., 1., 1.],
+        log::Level::Debug | log::Level::Trace => [0.6, 0.6, 0.6, 1.],
+    }
+}
+
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.level() <= self.level || self.next.as_ref().map_or(false, |n| n.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if record.level() <= self.level {
+            self.sink.write(TextSpan {
+                color: level_color(record.level()),
+                text: format!("[{}] {}\n", record.level(), record.args()),
+                time: Some(Instant::now()),
+                ..Default::default()
+            });
+        }
+        if let Some(next) = &self.next {
+            next.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(next) = &self.next {
+            next.flush();
+        }
+    }
+}
+
+impl Console {
+    /// Show everything logged through `logger` in this console.
+    ///
+    /// Call this before `ConsoleLogger::init`, which takes the logger away.
+    pub fn attach_logger(&mut self, logger: &ConsoleLogger) {
+        self.log_sink = Some(logger.sink.clone());
+    }
+}
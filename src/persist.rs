@@ -0,0 +1,84 @@
+use crate::{is_read_only, ConsoleError, ConsoleResult, IConsoleExt};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Every property under `root` that can be set, and its current value.
+fn values(root: &mut dyn cvar::IVisit) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+    cvar::console::walk(root, |path, node| {
+        if is_read_only(node.description()) {
+            return;
+        }
+        if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+            values.insert(path.to_string(), prop.get());
+        }
+    });
+    values
+}
+
+fn file_error(action: &str, path: &Path, e: &dyn std::fmt::Display) -> ConsoleResult {
+    ConsoleError::Custom(format!("Could not {} {}: {}", action, path.display(), e).into()).into()
+}
+
+/// Write the value of every property to `path`, as a RON map from property name to value.
+pub fn save_config(root: &mut dyn cvar::IVisit, path: &Path) -> ConsoleResult {
+    let values = values(root);
+    let text = match ron::ser::to_string_pretty(&values, ron::ser::PrettyConfig::default()) {
+        Ok(text) => text,
+        Err(e) => return file_error("save", path, &e),
+    };
+    match std::fs::write(path, text) {
+        Ok(()) => format!("Saved {} properties to {}", values.len(), path.display()).into(),
+        Err(e) => file_error("save", path, &e),
+    }
+}
+
+/// Set every property listed in a file written by `save_config`.
+///
+/// A bad value is reported on `console` and skipped, as are properties that no longer exist, so
+/// one stale entry doesn't stop the rest from loading.
+pub fn load_config(
+    root: &mut dyn cvar::IVisit,
+    path: &Path,
+    console: &mut dyn IConsoleExt,
+) -> ConsoleResult {
+    load_with(path, console, &mut |var, val| {
+        cvar::console::set(root, var, val)
+    })
+}
+
+/// `load_config`, setting each property through `set`.
+pub(crate) fn load_with(
+    path: &Path,
+    console: &mut dyn IConsoleExt,
+    set: &mut dyn FnMut(&str, &str) -> Result<bool, Box<dyn std::error::Error>>,
+) -> ConsoleResult {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return file_error("load", path, &e),
+    };
+    let values: BTreeMap<String, String> = match ron::de::from_str(&text) {
+        Ok(values) => values,
+        Err(e) => return file_error("load", path, &e),
+    };
+
+    let mut loaded = 0;
+    for (var, val) in &values {
+        match set(var, val) {
+            Ok(true) => loaded += 1,
+            Ok(false) => console.write_colored(
+                [1., 1., 0., 1.],
+                &format!("Skipped unknown property {}\n", var),
+            ),
+            Err(e) => console
+                .write_result(ConsoleError::InvalidValue(format!("{} for {}", e, var)).into()),
+        }
+    }
+    format!(
+        "Loaded {} of {} properties from {}",
+        loaded,
+        values.len(),
+        path.display()
+    )
+    .into()
+}
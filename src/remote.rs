@@ -0,0 +1,245 @@
+use crate::{Console, HeadlessConsole, IVisitExt};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+/// Longest line a client can send, in bytes. Anything longer closes the connection.
+const MAX_LINE_LEN: usize = 4096;
+
+/// How many clients can be connected at once. Any more are turned away.
+const MAX_CLIENTS: usize = 8;
+
+/// Accepts console commands over TCP, for debugging a running game from a terminal.
+///
+/// Each line received is run through a `HeadlessConsole`, so `;`, aliases, scripts, redirects and
+/// the confirmation for `reset` all work as they do in the window, and the text it writes is sent
+/// back. `watch` has no panel to show in, so it is refused. Nothing here blocks, so call `poll`
+/// once per frame from wherever the config lives.
+///
+/// `nc localhost 5555` is enough of a client.
+pub struct RemoteConsole {
+    listener: TcpListener,
+    password: Option<String>,
+    clients: Vec<Client>,
+    console: HeadlessConsole,
+}
+
+struct Client {
+    stream: TcpStream,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    authenticated: bool,
+    /// The client has finished sending, so whatever is left in `input` is its last line.
+    eof: bool,
+    closed: bool,
+}
+
+impl RemoteConsole {
+    /// Listen on the loopback interface only, so the port is not reachable from other machines.
+    pub fn localhost(port: u16) -> io::Result<RemoteConsole> {
+        RemoteConsole::bind((Ipv4Addr::LOCALHOST, port))
+    }
+
+    /// Listen on a loopback address, such as `"127.0.0.1:5555"`.
+    ///
+    /// Fails with `PermissionDenied` for any other address, which needs `bind_with_password`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<RemoteConsole> {
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+        if addrs.iter().any(|addr| !addr.ip().is_loopback()) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "only loopback addresses can be used without a password",
+            ));
+        }
+        RemoteConsole::listen(&addrs[..], None)
+    }
+
+    /// Listen on any address, only running commands from clients which first send `password`.
+    pub fn bind_with_password<A: ToSocketAddrs>(
+        addr: A,
+        password: &str,
+    ) -> io::Result<RemoteConsole> {
+        RemoteConsole::listen(addr, Some(password.to_string()))
+    }
+
+    fn listen<A: ToSocketAddrs>(addr: A, password: Option<String>) -> io::Result<RemoteConsole> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(RemoteConsole {
+            listener,
+            password,
+            clients: vec![],
+            console: remote_console(Console::new()),
+        })
+    }
+
+    /// Run remote commands with the settings of `console`, such as its aliases and guards.
+    pub fn with_console(mut self, console: Console) -> RemoteConsole {
+        self.console = remote_console(console);
+        self
+    }
+
+    /// Require clients to send this password as their first line before running commands.
+    pub fn with_password(mut self, password: &str) -> RemoteConsole {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept new connections, and run any complete command lines against `root`.
+    pub fn poll(&mut self, root: &mut dyn IVisitExt) {
+        while let Ok((mut stream, _)) = self.listener.accept() {
+            if self.clients.len() >= MAX_CLIENTS {
+                // best effort, the connection is dropped either way
+                let _ = stream.write_all(b"Too many connections\n");
+                continue;
+            }
+            if stream.set_nonblocking(true).is_ok() {
+                let mut client = Client {
+                    stream,
+                    input: vec![],
+                    output: vec![],
+                    authenticated: self.password.is_none(),
+                    eof: false,
+                    closed: false,
+                };
+                if !client.authenticated {
+                    client.output.extend_from_slice(b"Password:\n");
+                }
+                self.clients.push(client);
+            }
+        }
+
+        for client in &mut self.clients {
+            client.read();
+            while let Some(line) = client.next_line() {
+                if line.is_empty() {
+                    continue;
+                }
+                if client.authenticated {
+                    self.console.execute(root, &line);
+                    client.send(&self.console.take_text());
+                } else if Some(&line) == self.password.as_ref() {
+                    client.authenticated = true;
+                    client.send("OK\n");
+                } else {
+                    client.send("Invalid password\n");
+                    client.closed = true;
+                }
+            }
+            // lines sent before the end still get their replies, which close the connection once
+            // they are written
+            if client.eof {
+                client.closed = true;
+            }
+            client.flush();
+        }
+
+        self.clients
+            .retain(|client| !client.closed || !client.output.is_empty());
+    }
+
+    /// Every property changed by a remote command since the last call, with its new value.
+    pub fn take_changes(&mut self) -> Vec<(String, String)> {
+        self.console.console().take_changes()
+    }
+
+    /// Every command line run remotely since the last call, and whether it succeeded.
+    pub fn take_executed(&mut self) -> Vec<(String, bool)> {
+        self.console.console().take_executed()
+    }
+}
+
+/// `console`, set up to run remote commands.
+fn remote_console(mut console: Console) -> HeadlessConsole {
+    console.set_record_changes(true);
+    console.set_record_executed(true);
+    console.shows_watches = false;
+    HeadlessConsole::from_console(console)
+}
+
+impl Client {
+    fn read(&mut self) {
+        if self.eof || self.closed {
+            return;
+        }
+        let mut buf = [0; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => {
+                    self.input.extend_from_slice(&buf[..n]);
+                    let partial = self.input.iter().rev().take_while(|&&b| b != b'\n');
+                    if partial.count() > MAX_LINE_LEN {
+                        self.refuse("Line too long");
+                        break;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    self.output.clear();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// The next complete line of input, or what is left of it once the client has finished.
+    fn next_line(&mut self) -> Option<String> {
+        if self.closed {
+            return None;
+        }
+        let end = match self.input.iter().position(|&b| b == b'\n') {
+            Some(end) => end + 1,
+            None if self.eof && !self.input.is_empty() => self.input.len(),
+            None => return None,
+        };
+        if end > MAX_LINE_LEN + 1 {
+            self.refuse("Line too long");
+            return None;
+        }
+        let line = self.input.drain(..end).collect::<Vec<_>>();
+        Some(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    /// Tell the client why, then close the connection once that is written.
+    fn refuse(&mut self, reason: &str) {
+        self.input.clear();
+        self.send(reason);
+        self.closed = true;
+    }
+
+    fn send(&mut self, text: &str) {
+        self.output.extend_from_slice(text.as_bytes());
+        if !text.ends_with('\n') {
+            self.output.push(b'\n');
+        }
+    }
+
+    fn flush(&mut self) {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => {
+                    self.closed = true;
+                    self.output.clear();
+                }
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.closed = true;
+                    self.output.clear();
+                }
+            }
+        }
+    }
+}
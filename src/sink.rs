@@ -0,0 +1,78 @@
+use crate::{Console, TextSpan};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+/// Handle for writing to a console from any thread.
+///
+/// Get one from `Console::sink`, and clone it as needed. Output is queued and added to the
+/// console at the start of the next `build` (or `run_queued`), before any commands run that frame.
+///
+/// Each call adds its spans in one go, so a line written with a single `writeln` or
+/// `write_colored` is never split by output from other threads or commands. Separate calls from
+/// different threads may be interleaved with each other, in whatever order they took the lock.
+#[derive(Clone, Default)]
+pub struct ConsoleSink {
+    queue: Arc<Mutex<Vec<TextSpan>>>,
+}
+
+impl ConsoleSink {
+    fn lock(&self) -> MutexGuard<'_, Vec<TextSpan>> {
+        // a poisoned queue only means some other thread panicked while writing
+        match self.queue.lock() {
+            Ok(queue) => queue,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn write<S>(&self, text: S)
+    where
+        S: Into<TextSpan>,
+    {
+        let mut span = text.into();
+        span.time.get_or_insert_with(Instant::now);
+        self.lock().push(span);
+    }
+
+    pub fn writeln<S>(&self, text: S)
+    where
+        S: Into<TextSpan>,
+    {
+        let mut span = text.into();
+        span.text = span.text.trim_end().to_string();
+        if !span.text.is_empty() {
+            span.text.push_str("\n");
+            self.write(span);
+        }
+    }
+
+    pub fn write_colored(&self, color: [f32; 4], text: &str) {
+        self.write(TextSpan {
+            color,
+            text: text.to_string(),
+            ..Default::default()
+        });
+    }
+
+    /// Take everything written so far.
+    pub(crate) fn take(&self) -> Vec<TextSpan> {
+        std::mem::replace(&mut *self.lock(), vec![])
+    }
+}
+
+impl Console {
+    /// A handle other threads can use to write to this console.
+    pub fn sink(&self) -> ConsoleSink {
+        self.sink.clone()
+    }
+
+    /// Move output written through sinks into the console.
+    pub(crate) fn drain_sinks(&mut self) {
+        let mut spans = self.sink.take();
+        if let Some(sink) = &self.log_sink {
+            spans.append(&mut sink.take());
+        }
+        for span in spans {
+            self.write(span);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::{spans_text, Console, ConsoleResult, HeadlessConsole, IVisitExt, TextSpan};
+use crate::{CvarExt, VisitMutExt};
+
+/// Runs commands against a config without any window, for testing your own commands.
+///
+/// Lines run through a `HeadlessConsole`, so builtins like `help` and `reset`, `;`, aliases and
+/// scripts all behave exactly as they do in a `ConsoleWindow`.
+///
+/// ```ignore
+/// let mut console = TestConsole::new(MyConfig::default());
+/// console.run("width 120");
+/// assert_eq!(console.root().width, 120.);
+/// ```
+pub struct TestConsole<T> {
+    root: T,
+    console: HeadlessConsole,
+}
+
+impl<T: IVisitExt> TestConsole<T> {
+    pub fn new(root: T) -> TestConsole<T> {
+        TestConsole {
+            root,
+            console: HeadlessConsole::new(),
+        }
+    }
+
+    /// The console commands run in, for aliases, guards and anything else to set up first.
+    pub fn console(&mut self) -> &mut Console {
+        self.console.console()
+    }
+
+    /// Run a command line, returning everything that would have been written to the window.
+    pub fn run(&mut self, cmd: &str) -> Vec<TextSpan> {
+        self.console.execute(&mut self.root, cmd);
+        self.console.take_output()
+    }
+
+    /// Like `run`, but joins the output into a single string without colors.
+    pub fn run_text(&mut self, cmd: &str) -> String {
+        spans_text(&self.run(cmd))
+    }
+
+    pub fn get(&mut self, var: &str) -> ConsoleResult {
+        let root = &mut self.root;
+        VisitMutExt(|f, console| root.visit_mut_ext(f, console)).get(var)
+    }
+
+    pub fn set(&mut self, var: &str, val: &str) -> ConsoleResult {
+        let root = &mut self.root;
+        VisitMutExt(|f, console| root.visit_mut_ext(f, console)).set(var, val)
+    }
+
+    pub fn root(&mut self) -> &mut T {
+        &mut self.root
+    }
+
+    pub fn into_inner(self) -> T {
+        self.root
+    }
+}
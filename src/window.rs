@@ -0,0 +1,1433 @@
+use crate::{
+    common_prefix, format_hex_color, is_read_only, line_ranges, parse_hex_color, parse_range,
+    span_text, visit_commands, Console, ConsoleError, ConsoleWindowBuilder, CvarExt, IConsoleExt,
+    IVisitExt, SpanRole, SpanStyle, TextSpan, ValueFormat, VisitMutExt, Watch,
+};
+use imgui::{im_str, ImString};
+use std::collections::{BTreeSet, VecDeque};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Screen edge a docked console is attached to. See `ConsoleWindow::set_docked`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DockEdge {
+    Top,
+    Bottom,
+}
+
+/// Portion of the display height a docked console covers.
+const DOCK_HEIGHT: f32 = 0.4;
+
+/// Width of the overview strip beside the output.
+const OVERVIEW_WIDTH: f32 = 8.;
+
+/// Width of the slider panel beside the output.
+const SLIDER_PANEL_WIDTH: f32 = 220.;
+
+/// Shown in the prompt until something is typed, unless changed with `set_prompt_hint`.
+const DEFAULT_PROMPT_HINT: &str = "Type a command, or HELP for help";
+
+/// How many lines the prompt grows to before it scrolls instead.
+const MAX_PROMPT_LINES: usize = 5;
+
+/// Key index of R, which starts a history search with Ctrl held.
+///
+/// imgui only names the keys it uses itself, so this is R in winit's `VirtualKeyCode`, which is
+/// how amethyst-imgui and imgui-winit-support index keys. Change it with `set_search_key`.
+const DEFAULT_SEARCH_KEY: u32 = 27;
+
+/// Key index of F1, which switches `build_statusbar` between the bar and the full window.
+///
+/// A winit `VirtualKeyCode` like `DEFAULT_SEARCH_KEY`. Change it with `set_statusbar_key`.
+const DEFAULT_STATUSBAR_KEY: u32 = 37;
+
+/// Text formats for copying output. See `ConsoleWindow::copy_text`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CopyFormat {
+    Plain,
+    /// Plain text inside a fenced code block, ready to paste into an issue.
+    Markdown,
+    /// Only the commands that were entered, one per line. Ready to be saved as a script.
+    Commands,
+}
+
+/// Matches for the word being completed, kept between Tab presses to cycle through them.
+struct Completion {
+    /// Prompt text before the word being completed.
+    head: String,
+    candidates: Vec<String>,
+    /// Candidate currently in the prompt, if cycling has started.
+    index: Option<usize>,
+    /// What the prompt was set to. Anything else means it was edited, and completion starts over.
+    shown: String,
+}
+
+/// State of a Ctrl+R search back through history.
+struct HistorySearch {
+    query: ImString,
+    /// The query the match was found for, to notice when it is edited.
+    last_query: String,
+    /// Index of the matching history entry.
+    found: Option<usize>,
+    /// Give the search box focus on its first frame.
+    focus: bool,
+}
+
+/// The output split into lines, and the lines the filter lets through, kept in step with the
+/// scrollback so a frame only looks at what was written or dropped since the last one.
+#[derive(Debug, Default)]
+pub(crate) struct LineIndex {
+    /// Span range of each line, counted from the first span ever written, so dropping output
+    /// from the front doesn't move them.
+    lines: VecDeque<Range<usize>>,
+    /// Spans and lines dropped from the front so far.
+    dropped_spans: usize,
+    dropped_lines: usize,
+    /// The lowercased filter `shown` was found for.
+    filter: String,
+    /// Lines containing the filter, counted like `dropped_lines`.
+    shown: VecDeque<usize>,
+}
+
+impl LineIndex {
+    /// Catch up with `buf`, after `dropped_spans` spans have been dropped from its front in all,
+    /// and with `filter`. Returns whether the shown lines changed.
+    pub(crate) fn update(&mut self, buf: &[TextSpan], dropped_spans: usize, filter: &str) -> bool {
+        let mut changed = false;
+        if dropped_spans != self.dropped_spans {
+            while self
+                .lines
+                .front()
+                .map_or(false, |line| line.end <= dropped_spans)
+            {
+                self.lines.pop_front();
+                self.dropped_lines += 1;
+            }
+            if let Some(first) = self.lines.front_mut() {
+                first.start = first.start.max(dropped_spans);
+            }
+            while self
+                .shown
+                .front()
+                .map_or(false, |&n| n < self.dropped_lines)
+            {
+                self.shown.pop_front();
+            }
+            self.dropped_spans = dropped_spans;
+            changed = true;
+        }
+
+        let filter = filter.to_lowercase();
+        if filter != self.filter {
+            self.filter = filter;
+            self.shown.clear();
+            for n in 0..self.lines.len() {
+                if self.matches(buf, n) {
+                    self.shown.push_back(self.dropped_lines + n);
+                }
+            }
+            changed = true;
+        }
+
+        let end = dropped_spans + buf.len();
+        let from = match self.lines.back() {
+            Some(last) if last.end == end => return changed,
+            // the last line may still be open, so it is split again along with what follows
+            Some(last) if last.end < end => last.start,
+            // the output went without being counted as dropped, so start over
+            _ => {
+                self.dropped_lines += self.lines.len();
+                self.lines.clear();
+                self.shown.clear();
+                dropped_spans
+            }
+        };
+        if self.lines.back().map(|last| last.start) == Some(from) {
+            self.lines.pop_back();
+            if self.shown.back() == Some(&(self.dropped_lines + self.lines.len())) {
+                self.shown.pop_back();
+            }
+        }
+        for range in line_ranges(&buf[from - dropped_spans..]) {
+            self.lines.push_back(from + range.start..from + range.end);
+            if self.matches(buf, self.lines.len() - 1) {
+                self.shown
+                    .push_back(self.dropped_lines + self.lines.len() - 1);
+            }
+        }
+        true
+    }
+
+    /// Spans of line `n` in the output.
+    pub(crate) fn line(&self, n: usize) -> Range<usize> {
+        let line = &self.lines[n];
+        line.start - self.dropped_spans..line.end - self.dropped_spans
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// How many lines the filter lets through.
+    pub(crate) fn shown_len(&self) -> usize {
+        self.shown.len()
+    }
+
+    /// The `i`th line the filter lets through.
+    pub(crate) fn shown(&self, i: usize) -> usize {
+        self.shown[i] - self.dropped_lines
+    }
+
+    fn matches(&self, buf: &[TextSpan], n: usize) -> bool {
+        if self.filter.is_empty() {
+            return true;
+        }
+        let mut text = String::new();
+        for span in &buf[self.line(n)] {
+            span_text(span, &mut text);
+        }
+        text.to_lowercase().contains(&self.filter)
+    }
+}
+
+/// imgui frontend for cvars.
+/// Call `build` during your rendering stage.
+///
+/// Will handle reading user input, dispatching commands, and displaying output. Commands are run
+/// by the `Console` inside, which the window derefs to, so its setters can be called on the window
+/// directly.
+pub struct ConsoleWindow {
+    pub(crate) console: Console,
+    pub(crate) prompt: ImString,
+    /// Greyed out text shown in the empty prompt.
+    prompt_hint: Option<String>,
+    /// Entry of the history shown in the prompt, or None when on a new line.
+    pub(crate) history_pos: Option<usize>,
+    /// Flipped to give the prompt a new id, so imgui takes the text from our buffer again.
+    prompt_id: bool,
+    completion: Option<Completion>,
+    search: Option<HistorySearch>,
+    search_key: u32,
+    statusbar_key: u32,
+    /// `build_statusbar` draws the full window instead of the bar.
+    statusbar_expanded: bool,
+    /// Focus the prompt on the next frame, after something else had it.
+    pub(crate) focus_prompt: bool,
+    stick_to_bottom: bool,
+    /// Following was turned off from the toolbar, so reaching the bottom doesn't turn it back on.
+    follow_paused: bool,
+    last_scroll_y: f32,
+    pub(crate) prompt_active: bool,
+    dock: Option<DockEdge>,
+    /// Position and size of the screen region drawn over, with no window decoration.
+    overlay: Option<([f32; 2], [f32; 2])>,
+    overview: bool,
+    scroll_to: Option<f32>,
+    watch_highlight: [f32; 4],
+    watch_highlight_duration: Duration,
+    watch_changed_only: bool,
+    pub(crate) selected: BTreeSet<usize>,
+    /// Height of each output line when it was last drawn, so lines scrolled out of view can be
+    /// skipped over instead of drawn. None for lines not drawn yet.
+    pub(crate) line_heights: Vec<Option<f32>>,
+    /// Output width the heights were measured at, as wrapping depends on it.
+    line_heights_width: f32,
+    pub(crate) lines: LineIndex,
+    /// Offset of each shown line from the top of the output, and of the end after the last,
+    /// from the heights above. Empty when something moved, until the next frame works them out.
+    line_tops: Vec<f32>,
+    /// Line the current selection started from, which shift+click and dragging extend from.
+    select_anchor: Option<usize>,
+    dragging: bool,
+    wrap: bool,
+    color_panel: bool,
+    /// Whether the slider panel is shown, toggled from its button.
+    sliders: bool,
+    /// Only lines containing this are shown, ignoring case. Shows everything when empty.
+    filter: ImString,
+    timestamps: bool,
+    /// The console's `dropped_lines` and `resets` as of the last `sync`.
+    seen_dropped_lines: usize,
+    seen_resets: usize,
+}
+
+impl ConsoleWindow {
+    pub fn new() -> Self {
+        ConsoleWindow::with_console(Console::new())
+    }
+
+    /// Draw `console`, keeping its output, history and settings.
+    pub fn with_console(console: Console) -> Self {
+        ConsoleWindow {
+            seen_dropped_lines: console.dropped_lines,
+            seen_resets: console.resets,
+            console,
+            prompt: ImString::with_capacity(100),
+            prompt_hint: Some(DEFAULT_PROMPT_HINT.to_string()),
+            history_pos: None,
+            prompt_id: false,
+            completion: None,
+            search: None,
+            search_key: DEFAULT_SEARCH_KEY,
+            statusbar_key: DEFAULT_STATUSBAR_KEY,
+            statusbar_expanded: false,
+            focus_prompt: false,
+            stick_to_bottom: true,
+            follow_paused: false,
+            last_scroll_y: 0.,
+            prompt_active: false,
+            dock: None,
+            overlay: None,
+            overview: false,
+            scroll_to: None,
+            watch_highlight: [1., 1., 0., 1.],
+            watch_highlight_duration: Duration::from_secs(1),
+            watch_changed_only: false,
+            selected: BTreeSet::new(),
+            line_heights: vec![],
+            line_heights_width: 0.,
+            lines: LineIndex::default(),
+            line_tops: vec![],
+            select_anchor: None,
+            dragging: false,
+            wrap: false,
+            color_panel: false,
+            sliders: false,
+            filter: ImString::with_capacity(64),
+            timestamps: false,
+        }
+    }
+
+    /// The console behind the window, leaving the window behind.
+    pub fn into_console(self) -> Console {
+        self.console
+    }
+}
+
+impl Default for ConsoleWindow {
+    fn default() -> Self {
+        ConsoleWindow::new()
+    }
+}
+
+impl std::ops::Deref for ConsoleWindow {
+    type Target = Console;
+
+    fn deref(&self) -> &Console {
+        &self.console
+    }
+}
+
+impl std::ops::DerefMut for ConsoleWindow {
+    fn deref_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+}
+
+impl ConsoleWindow {
+    /// Configure a new window with chained calls, instead of a setter for each option.
+    pub fn builder() -> ConsoleWindowBuilder {
+        ConsoleWindowBuilder::new()
+    }
+
+    pub fn clear(&mut self) {
+        self.console.clear();
+        self.sync();
+    }
+
+    /// The selected lines of output, or all of them if nothing is selected, as text.
+    ///
+    /// Lines are selected by clicking or dragging over them. Hold ctrl to add single lines, or
+    /// shift to select everything up to the clicked line.
+    pub fn copy_text(&self, format: CopyFormat) -> String {
+        self.copy_lines(format, &|n, _| {
+            self.selected.is_empty() || self.selected.contains(&n)
+        })
+    }
+
+    /// The last `count` lines of output as text, ignoring the selection.
+    pub fn copy_last_lines(&self, count: usize, format: CopyFormat) -> String {
+        self.copy_lines(format, &|n, total| n + count >= total)
+    }
+
+    /// Lines for which `keep(line, line count)` is true, as text.
+    fn copy_lines(&self, format: CopyFormat, keep: &dyn Fn(usize, usize) -> bool) -> String {
+        let buf = self.console.output.buf.iter().collect::<Vec<_>>();
+        let ranges = line_ranges(buf.iter().copied());
+        let total = ranges.len();
+        let lines = ranges
+            .into_iter()
+            .enumerate()
+            .filter(|(n, _)| keep(*n, total))
+            .map(|(_, range)| &buf[range])
+            .collect::<Vec<_>>();
+
+        let plain = || {
+            let mut out = String::new();
+            for line in &lines {
+                if let Some(stamp) = timestamp(self.timestamps, self.console.started, line[0]) {
+                    out.push_str(&stamp);
+                }
+                for span in line.iter() {
+                    span_text(span, &mut out);
+                }
+            }
+            out
+        };
+        match format {
+            CopyFormat::Plain => plain(),
+            CopyFormat::Markdown => format!("```\n{}\n```\n", plain().trim_end()),
+            CopyFormat::Commands => lines
+                .iter()
+                .flat_map(|line| line.iter())
+                .filter(|span| span.role == SpanRole::Input)
+                .map(|span| span.text.as_str())
+                .collect(),
+        }
+    }
+
+    /// Key index which, with Ctrl, searches back through history.
+    ///
+    /// Only needed if your imgui backend doesn't index keys with winit's `VirtualKeyCode`.
+    pub fn set_search_key(&mut self, key: u32) {
+        self.search_key = key;
+    }
+
+    /// Key index which switches `build_statusbar` between the bar and the full window.
+    ///
+    /// Only needed if your imgui backend doesn't index keys with winit's `VirtualKeyCode`.
+    pub fn set_statusbar_key(&mut self, key: u32) {
+        self.statusbar_key = key;
+    }
+
+    /// Switch `build_statusbar` between the bar and the full window, as its key does.
+    pub fn toggle_statusbar(&mut self) {
+        self.statusbar_expanded = !self.statusbar_expanded;
+        // so typing carries on in whichever prompt is shown now
+        self.focus_prompt = true;
+    }
+
+    /// Whether `build_statusbar` is drawing the full window.
+    pub fn is_statusbar_expanded(&self) -> bool {
+        self.statusbar_expanded
+    }
+
+    /// Whether the prompt has keyboard focus, as of the last `build`.
+    pub fn has_focus(&self) -> bool {
+        self.prompt_active
+    }
+
+    /// Change the greyed out hint shown while the prompt is empty, or None to show nothing.
+    pub fn set_prompt_hint(&mut self, hint: Option<&str>) {
+        self.prompt_hint = hint.map(|h| h.to_string());
+    }
+
+    /// Attach the console to an edge of the screen, Quake style, instead of floating.
+    ///
+    /// The window spans the full display width and can't be moved or resized.
+    pub fn set_docked(&mut self, edge: DockEdge) {
+        self.dock = Some(edge);
+        self.overlay = None;
+    }
+
+    /// Draw the console straight over the game in a region of the screen, as a HUD style log.
+    ///
+    /// There is no title bar or background, and the window can't be moved or resized, but the
+    /// prompt works as normal.
+    pub fn set_overlay(&mut self, position: [f32; 2], size: [f32; 2]) {
+        self.overlay = Some((position, size));
+        self.dock = None;
+    }
+
+    /// Return the console to a clean slate, as `Console::reset_state` does, and empty the prompt
+    /// and filter too. Settings such as docking and the overview strip are kept.
+    pub fn reset_state(&mut self) {
+        self.console.reset_state();
+        self.sync();
+    }
+
+    /// Catch up with what the console did to its output and state since the last frame, such as
+    /// lines it dropped, or a `console_reset` run by a command.
+    pub(crate) fn sync(&mut self) {
+        if self.console.dropped_lines != self.seen_dropped_lines {
+            let dropped = self.console.dropped_lines - self.seen_dropped_lines;
+            let dropped = dropped.min(self.line_heights.len());
+            self.line_heights.drain(..dropped);
+            // line numbers have all moved
+            self.selected.clear();
+            self.select_anchor = None;
+            self.seen_dropped_lines = self.console.dropped_lines;
+        }
+        if self.console.resets != self.seen_resets {
+            self.prompt.clear();
+            self.history_pos = None;
+            self.completion = None;
+            self.search = None;
+            self.filter.clear();
+            self.stick_to_bottom = true;
+            self.follow_paused = false;
+            self.last_scroll_y = 0.;
+            self.scroll_to = None;
+            self.seen_resets = self.console.resets;
+        }
+    }
+
+    /// Keep the newest output in view, as the Follow checkbox does. On by default.
+    ///
+    /// Scrolling up stops following until the view is scrolled back to the bottom. Turning it
+    /// off here stops it until it is turned back on.
+    pub fn set_follow(&mut self, follow: bool) {
+        self.stick_to_bottom = follow;
+        self.follow_paused = !follow;
+        if follow {
+            self.scroll_to = Some(1.);
+        }
+    }
+
+    /// Show a collapsible panel with a color picker for every property holding a color.
+    ///
+    /// Any property whose value is written as `#rrggbb` or `#rrggbbaa` counts, and picking a new
+    /// color sets it just like typing `set`.
+    pub fn set_color_panel(&mut self, color_panel: bool) {
+        self.color_panel = color_panel;
+    }
+
+    /// Show or hide the panel of sliders for number properties, as the Sliders button does.
+    pub fn set_slider_panel(&mut self, sliders: bool) {
+        self.sliders = sliders;
+    }
+
+    /// Color watched values are drawn in for `duration` after they change.
+    pub fn set_watch_highlight(&mut self, color: [f32; 4], duration: Duration) {
+        self.watch_highlight = color;
+        self.watch_highlight_duration = duration;
+    }
+
+    /// Only show watched values which changed within the highlight duration.
+    pub fn set_watch_changed_only(&mut self, changed_only: bool) {
+        self.watch_changed_only = changed_only;
+    }
+
+    /// Show a strip beside the output marking where errors and warnings are.
+    ///
+    /// Clicking the strip jumps to that part of the output.
+    pub fn set_overview(&mut self, overview: bool) {
+        self.overview = overview;
+    }
+
+    /// Start each line of output with the time since the console was created, as `[HH:MM:SS]`.
+    ///
+    /// While shown, timestamps are also included when copying output as text.
+    pub fn show_timestamps(&mut self, timestamps: bool) {
+        self.timestamps = timestamps;
+    }
+
+    /// Wrap long lines of output at the window edge instead of scrolling sideways.
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    /// Go back to a normal movable window. This is the default.
+    pub fn set_floating(&mut self) {
+        self.dock = None;
+        self.overlay = None;
+    }
+
+    pub fn build(&mut self, ui: &imgui::Ui, window: imgui::Window, root: &mut dyn IVisitExt) {
+        self.console.run_queued(root);
+        self.sync();
+        let window = match self.dock {
+            Some(edge) => {
+                let [width, height] = ui.io().display_size;
+                let docked_height = height * DOCK_HEIGHT;
+                let y = match edge {
+                    DockEdge::Top => 0.,
+                    DockEdge::Bottom => height - docked_height,
+                };
+                window
+                    .position([0., y], imgui::Condition::Always)
+                    .size([width, docked_height], imgui::Condition::Always)
+                    .movable(false)
+                    .resizable(false)
+                    .collapsible(false)
+            }
+            None => match self.overlay {
+                Some((position, size)) => window
+                    .position(position, imgui::Condition::Always)
+                    .size(size, imgui::Condition::Always)
+                    .title_bar(false)
+                    .draw_background(false)
+                    .movable(false)
+                    .resizable(false)
+                    .collapsible(false),
+                None => window.size([520., 600.], imgui::Condition::FirstUseEver),
+            },
+        };
+        window.build(ui, move || {
+            if ui.is_item_hovered() {
+                ui.popup(im_str!("context_menu"), || {
+                    if imgui::MenuItem::new(im_str!("Close")).build(ui) {
+                        //self.close();
+                    }
+                })
+            }
+
+            let clear = ui.button(im_str!("Clear"), [0., 0.]);
+            ui.same_line(0.);
+            let copy = ui.button(im_str!("Copy"), [0., 0.]);
+            ui.same_line(0.);
+            if ui.button(im_str!("Sliders"), [0., 0.]) {
+                self.sliders = !self.sliders;
+            }
+            ui.same_line(0.);
+            let mut follow = self.stick_to_bottom;
+            if ui.checkbox(im_str!("Follow"), &mut follow) {
+                self.set_follow(follow);
+            }
+            ui.same_line(0.);
+            ui.push_item_width(200.);
+            imgui::InputText::new(ui, im_str!("Filter"), &mut self.filter).build();
+            ui.pop_item_width();
+            ui.separator();
+
+            if !self.console.watches.is_empty() {
+                self.draw_watches(ui, root);
+                ui.separator();
+            }
+
+            if self.color_panel && ui.collapsing_header(im_str!("Colors")).build() {
+                self.draw_colors(ui, root);
+                ui.separator();
+            }
+
+            let extra_lines = (self.prompt_lines() - 1) as f32 * ui.text_line_height();
+            let footer_height_to_reserve = 1.5 * ui.frame_height_with_spacing() + extra_lines;
+            let mut child_width = if self.overview { -OVERVIEW_WIDTH } else { 0. };
+            if self.sliders {
+                child_width -= SLIDER_PANEL_WIDTH;
+            }
+            let child = imgui::ChildWindow::new(imgui::Id::Str("scrolling"))
+                .size([child_width, -footer_height_to_reserve])
+                .horizontal_scrollbar(!self.wrap);
+            child.build(ui, || {
+                // Decide before drawing, while the scroll limits still describe last frame's
+                // content. Only scrolling up unpins the view, so a burst of output or a resize
+                // won't.
+                let scroll_y = ui.scroll_y();
+                if self.follow_paused {
+                    // left alone until the Follow box is ticked again
+                } else if scroll_y >= ui.scroll_max_y() - 1. {
+                    self.stick_to_bottom = true;
+                } else if scroll_y < self.last_scroll_y {
+                    self.stick_to_bottom = false;
+                }
+                self.last_scroll_y = scroll_y;
+                if let Some(fraction) = self.scroll_to.take() {
+                    ui.set_scroll_y(fraction * ui.scroll_max_y());
+                    self.stick_to_bottom = fraction >= 1. && !self.follow_paused;
+                }
+                if clear {
+                    self.clear();
+                }
+                let mut copy_as = if copy { Some(CopyFormat::Plain) } else { None };
+                if ui.is_window_hovered() && ui.is_mouse_clicked(imgui::MouseButton::Right) {
+                    ui.open_popup(im_str!("copy_as"));
+                }
+                ui.popup(im_str!("copy_as"), || {
+                    if imgui::MenuItem::new(im_str!("Copy as text")).build(ui) {
+                        copy_as = Some(CopyFormat::Plain);
+                    }
+                    if imgui::MenuItem::new(im_str!("Copy as Markdown")).build(ui) {
+                        copy_as = Some(CopyFormat::Markdown);
+                    }
+                    if imgui::MenuItem::new(im_str!("Copy commands")).build(ui) {
+                        copy_as = Some(CopyFormat::Commands);
+                    }
+                    ui.separator();
+                    if imgui::MenuItem::new(im_str!("Copy last 10 lines")).build(ui) {
+                        let text = self.copy_last_lines(10, CopyFormat::Plain);
+                        ui.set_clipboard_text(&ImString::new(text));
+                    }
+                });
+                if let Some(format) = copy_as {
+                    ui.set_clipboard_text(&ImString::new(self.copy_text(format)));
+                }
+
+                let style = ui.push_style_var(imgui::StyleVar::ItemSpacing([0., 0.]));
+
+                // the scrollback is a ring buffer, but lines are drawn from slices of it
+                let buf = &*self.console.output.buf.make_contiguous();
+                let mut link = None;
+                let selected = &mut self.selected;
+                let anchor = &mut self.select_anchor;
+                let clicked =
+                    ui.is_window_hovered() && ui.is_mouse_clicked(imgui::MouseButton::Left);
+                let mouse_down = ui.is_mouse_down(imgui::MouseButton::Left);
+                let dragging = &mut self.dragging;
+                *dragging = *dragging && mouse_down;
+                let mouse_y = ui.io().mouse_pos[1];
+                let (left, width) = (ui.window_pos()[0], ui.window_size()[0]);
+                let (view_top, view_bottom) =
+                    (ui.window_pos()[1], ui.window_pos()[1] + ui.window_size()[1]);
+                let wrap = self.wrap;
+                if wrap && width != self.line_heights_width {
+                    self.line_heights.clear();
+                    self.line_tops.clear();
+                }
+                self.line_heights_width = width;
+                let filter = self.filter.to_str();
+                if self.lines.update(buf, self.console.dropped_spans, filter) {
+                    self.line_tops.clear();
+                }
+                let lines = &self.lines;
+                let heights = &mut self.line_heights;
+                if heights.len() < lines.len() {
+                    heights.resize(lines.len(), None);
+                }
+                // lines not drawn yet are taken to be a single row until they are
+                let row = ui.text_line_height();
+                let tops = &mut self.line_tops;
+                if tops.is_empty() {
+                    tops.push(0.);
+                    for i in 0..lines.shown_len() {
+                        let top = tops[i] + heights[lines.shown(i)].unwrap_or(row);
+                        tops.push(top);
+                    }
+                }
+
+                // only the shown lines in view are drawn, with the space of those above and
+                // below kept, which keeps huge scrollbacks cheap to draw
+                let origin = ui.cursor_screen_pos()[1];
+                let first = tops[1..].partition_point(|bottom| origin + bottom < view_top);
+                let last = tops[..lines.shown_len()]
+                    .partition_point(|top| origin + top <= view_bottom)
+                    .max(first);
+                if tops[first] > 0. {
+                    ui.dummy([0., tops[first]]);
+                }
+                let mut moved = false;
+                let (timestamps, started) = (self.timestamps, self.console.started);
+                for n in (first..last).map(|i| lines.shown(i)) {
+                    let range = lines.line(n);
+                    let top = ui.cursor_screen_pos()[1];
+                    if let Some(stamp) = timestamp(timestamps, started, &buf[range.start]) {
+                        ui.text_disabled(stamp);
+                        ui.same_line(0.);
+                    }
+                    let line_link = draw_spans(ui, &buf[range], &format!("line{}", n), wrap);
+                    let bottom = ui.cursor_screen_pos()[1];
+                    moved |= heights[n] != Some(bottom - top);
+                    heights[n] = Some(bottom - top);
+                    if line_link.is_some() {
+                        // following a link shouldn't also select its line
+                        link = line_link;
+                        continue;
+                    }
+
+                    let hovered = mouse_y >= top && mouse_y < bottom;
+                    let select_to = |selected: &mut BTreeSet<usize>, from: usize| {
+                        selected.clear();
+                        selected.extend(from.min(n)..=from.max(n));
+                    };
+                    if clicked && hovered {
+                        match *anchor {
+                            Some(from) if ui.io().key_shift => select_to(selected, from),
+                            _ if ui.io().key_ctrl => {
+                                if !selected.remove(&n) {
+                                    selected.insert(n);
+                                }
+                                *anchor = Some(n);
+                            }
+                            _ if selected.len() == 1 && selected.contains(&n) => {
+                                selected.clear();
+                                *anchor = None;
+                            }
+                            _ => {
+                                selected.clear();
+                                selected.insert(n);
+                                *anchor = Some(n);
+                                *dragging = true;
+                            }
+                        }
+                    } else if *dragging && hovered {
+                        if let Some(from) = *anchor {
+                            select_to(selected, from);
+                        }
+                    }
+                    if selected.contains(&n) {
+                        ui.get_window_draw_list()
+                            .add_rect([left, top], [left + width, bottom], [0.3, 0.5, 1., 0.25])
+                            .filled(true)
+                            .build();
+                    }
+                }
+                let below = tops[lines.shown_len()] - tops[last];
+                if below > 0. {
+                    ui.dummy([0., below]);
+                }
+                if moved {
+                    tops.clear();
+                }
+
+                style.pop(ui);
+                if let Some(cmd) = link {
+                    self.set_prompt_text(&cmd);
+                }
+
+                if self.stick_to_bottom {
+                    ui.set_scroll_here_y_with_ratio(1.0);
+                }
+            });
+            if self.overview {
+                ui.same_line(0.);
+                self.draw_overview(ui, footer_height_to_reserve);
+            }
+            if self.sliders {
+                ui.same_line(0.);
+                let panel = imgui::ChildWindow::new(imgui::Id::Str("sliders"))
+                    .size([SLIDER_PANEL_WIDTH, -footer_height_to_reserve]);
+                panel.build(ui, || self.draw_sliders(ui, root));
+            }
+
+            ui.separator();
+            self.build_prompt(ui, root);
+        });
+    }
+
+    /// Draw a single line bar along the bottom of the screen, with the latest output and a prompt.
+    ///
+    /// A lightweight alternative to `build` for games that don't want a full window. Both share
+    /// the same output and history, so you can switch between them at any time. F1 expands the bar
+    /// into the full window and back, see `set_statusbar_key` and `toggle_statusbar`.
+    pub fn build_statusbar(&mut self, ui: &imgui::Ui, root: &mut dyn IVisitExt) {
+        if ui.is_key_pressed(self.statusbar_key) {
+            self.toggle_statusbar();
+        }
+        if self.statusbar_expanded {
+            self.build(ui, imgui::Window::new(im_str!("Console")), root);
+            return;
+        }
+        self.console.run_queued(root);
+        self.sync();
+
+        let [width, height] = ui.io().display_size;
+        let bar_height = 2. * ui.frame_height_with_spacing();
+        imgui::Window::new(im_str!("Console##statusbar"))
+            .position([0., height - bar_height], imgui::Condition::Always)
+            .size([width, bar_height], imgui::Condition::Always)
+            .title_bar(false)
+            .movable(false)
+            .resizable(false)
+            .collapsible(false)
+            .scroll_bar(false)
+            .build(ui, || {
+                let last = self
+                    .console
+                    .output
+                    .buf
+                    .iter()
+                    .rev()
+                    .find(|span| !span.text.trim().is_empty());
+                if let Some(span) = last {
+                    ui.text_colored(span.color, span.text.trim_end());
+                } else {
+                    ui.text("");
+                }
+                self.build_prompt(ui, root);
+            });
+    }
+
+    /// Draw the command input, handling completion and running whatever is entered.
+    fn build_prompt(&mut self, ui: &imgui::Ui, root: &mut dyn IVisitExt) {
+        let search_key = ui.io().key_ctrl && ui.is_key_pressed(self.search_key);
+        if self.search.is_some() {
+            self.build_search(ui, search_key);
+            return;
+        }
+
+        let mut reclaim_focus = self.focus_prompt;
+        self.focus_prompt = false;
+        let label = if self.prompt_id {
+            im_str!("cmd##history")
+        } else {
+            im_str!("cmd")
+        };
+        // Padding around the text, on top of the lines themselves
+        let padding = ui.frame_height_with_spacing() - ui.text_line_height_with_spacing();
+        let height = padding + self.prompt_lines() as f32 * ui.text_line_height();
+        let start = ui.cursor_pos();
+        // grown as needed, as text outside ASCII takes several bytes a character
+        let input = imgui::InputTextMultiline::new(ui, label, &mut self.prompt, [-1., height])
+            .resize_buffer(true)
+            .enter_returns_true(true)
+            .ctrl_enter_for_new_line(true)
+            .build();
+        // Tab moves focus away from the input, so check against last frame's state too
+        let active = ui.is_item_active();
+        // imgui has no hint for multi-line inputs, so draw it over the empty box instead
+        match &self.prompt_hint {
+            Some(hint) if !active && self.prompt.to_str().is_empty() => {
+                let after = ui.cursor_pos();
+                ui.set_cursor_pos([start[0] + padding / 2., start[1] + padding / 2.]);
+                ui.text_disabled(hint);
+                ui.set_cursor_pos(after);
+            }
+            _ => {}
+        }
+        if (active || self.prompt_active) && ui.is_key_pressed(ui.key_index(imgui::Key::Tab)) {
+            self.cycle_completion(root, ui.io().key_shift);
+            reclaim_focus = true;
+        }
+        self.prompt_active = active;
+        if active && search_key {
+            self.search = Some(HistorySearch {
+                query: ImString::with_capacity(100),
+                last_query: String::new(),
+                found: None,
+                focus: true,
+            });
+        }
+        // Arrows move between lines of a multi-line command rather than through history
+        let single_line = !self.prompt.to_str().contains('\n');
+        if active && single_line {
+            let up = ui.is_key_pressed(ui.key_index(imgui::Key::UpArrow));
+            let down = ui.is_key_pressed(ui.key_index(imgui::Key::DownArrow));
+            // the history may have changed since, so anything past its end is a new line
+            let len = self.console.history.len();
+            let mut pos = self.history_pos.filter(|pos| *pos < len).unwrap_or(len);
+            if (up && pos > 0) || (down && pos < len) {
+                if up {
+                    pos -= 1;
+                } else {
+                    pos += 1;
+                }
+                self.history_pos = Some(pos).filter(|pos| *pos < len);
+                let text = self.console.history.get(pos).cloned().unwrap_or_default();
+                self.set_prompt_buffer(&text);
+                // imgui keeps its own copy of the text while editing, and there is no history
+                // callback to change it. Re-creating the input makes it read ours instead.
+                self.prompt_id = !self.prompt_id;
+                reclaim_focus = true;
+            }
+        }
+        if input && ui.io().key_shift {
+            // imgui submits on any Enter, so Shift+Enter has to add its own newline
+            let text = format!("{}\n", self.prompt.to_str());
+            self.set_prompt_buffer(&text);
+            self.prompt_id = !self.prompt_id;
+            reclaim_focus = true;
+        } else if input {
+            // Each line of a pasted script is its own command, run in order like `a; b`
+            let cmd = self
+                .prompt
+                .to_str()
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("; ");
+            self.console.push_history(&cmd);
+            self.history_pos = None;
+            self.echo_cmd(&cmd);
+            self.run_cmd(root, cmd);
+            self.prompt.clear();
+            reclaim_focus = true;
+        }
+
+        ui.set_item_default_focus();
+        if reclaim_focus {
+            ui.set_keyboard_focus_here(imgui::FocusedWidget::Previous);
+        }
+    }
+
+    /// Draw the history search in place of the prompt. `again` looks for an older match.
+    ///
+    /// Enter puts the match in the prompt and Escape goes back to what was there.
+    fn build_search(&mut self, ui: &imgui::Ui, again: bool) {
+        let history = &self.console.history;
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return,
+        };
+        let accept = imgui::InputText::new(ui, im_str!("reverse-i-search"), &mut search.query)
+            .resize_buffer(true)
+            .enter_returns_true(true)
+            .build();
+        if search.focus {
+            ui.set_keyboard_focus_here(imgui::FocusedWidget::Previous);
+            search.focus = false;
+        }
+
+        let query = search.query.to_str().to_string();
+        let find = |before: usize| {
+            if query.is_empty() {
+                return None;
+            }
+            history[..before]
+                .iter()
+                .rposition(|cmd| cmd.contains(&query))
+        };
+        if query != search.last_query {
+            search.found = find(history.len());
+        } else if again {
+            // Stay on the oldest match once there are no more
+            if let Some(older) = search.found.and_then(find) {
+                search.found = Some(older);
+            }
+        }
+        search.last_query = query;
+
+        let found = search.found.map(|i| history[i].clone());
+        match &found {
+            Some(cmd) => ui.text(cmd),
+            None => ui.text_disabled("no match"),
+        }
+
+        let cancel = ui.is_key_pressed(ui.key_index(imgui::Key::Escape));
+        if accept || cancel {
+            if let (true, Some(cmd)) = (accept, found) {
+                self.set_prompt_buffer(&cmd);
+                self.prompt_id = !self.prompt_id;
+            }
+            self.search = None;
+            self.focus_prompt = true;
+        }
+    }
+
+    /// Draw the current value of every watched property, highlighting recent changes.
+    fn draw_watches(&mut self, ui: &imgui::Ui, root: &mut dyn IVisitExt) {
+        let mut root = VisitMutExt(|f, console| {
+            root.visit_mut_ext(f, console);
+        });
+        let now = Instant::now();
+        let duration = self.watch_highlight_duration;
+        for watch in &mut self.console.watches {
+            let value = root.get(&watch.path).0.ok();
+            if watch.value.is_some() && value != watch.value {
+                watch.changed = Some(now);
+            }
+            watch.value = value;
+
+            let recent = watch.changed.map_or(false, |t| now - t < duration);
+            if self.watch_changed_only && !recent {
+                continue;
+            }
+            let color = if recent {
+                self.watch_highlight
+            } else {
+                [1., 1., 1., 1.]
+            };
+            ui.text_colored(color, watch_label(watch, &self.console.value_format));
+        }
+    }
+
+    /// Draw a color picker for every property holding a color, setting it when edited.
+    fn draw_colors(&mut self, ui: &imgui::Ui, root: &mut dyn IVisitExt) {
+        let mut root = VisitMutExt(|f, console| {
+            root.visit_mut_ext(f, console);
+        });
+        let mut colors = vec![];
+        cvar::console::walk(&mut root, |path, node| {
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                if let Some(color) = parse_hex_color(&prop.get()) {
+                    colors.push((path.to_string(), color));
+                }
+            }
+        });
+        for (path, mut color) in colors {
+            if imgui::ColorEdit::new(&ImString::new(path.as_str()), &mut color).build(ui) {
+                if let Err(e) = &*root.set(&path, &format_hex_color(color)) {
+                    self.console.output.write_result(e.clone().into());
+                }
+            }
+        }
+        self.console
+            .record_changes(std::mem::replace(&mut root.changes, vec![]));
+    }
+
+    /// Draw a slider for every number property, or a drag box for those without a range.
+    fn draw_sliders(&mut self, ui: &imgui::Ui, root: &mut dyn IVisitExt) {
+        let mut root = VisitMutExt(|f, console| {
+            root.visit_mut_ext(f, console);
+        });
+        let mut numbers = vec![];
+        cvar::console::walk(&mut root, |path, node| {
+            let desc = node.description().to_string();
+            if is_read_only(&desc) {
+                return;
+            }
+            if let cvar::NodeMut::Prop(prop) = node.as_node_mut() {
+                if let Ok(value) = prop.get().parse::<f32>() {
+                    numbers.push((path.to_string(), value, parse_range(&desc)));
+                }
+            }
+        });
+        if numbers.is_empty() {
+            ui.text_disabled("No number properties");
+        }
+        for (path, mut value, range) in numbers {
+            let label = ImString::new(path.as_str());
+            let changed = match range {
+                Some((min, max)) => ui.slider_float(&label, &mut value, min, max).build(),
+                None => {
+                    let speed = (value.abs() / 100.).max(0.01);
+                    ui.drag_float(&label, &mut value).speed(speed).build()
+                }
+            };
+            if changed {
+                // whole number properties won't take a fraction, so round for those
+                let result = root.set(&path, &value.to_string());
+                let result = match &*result {
+                    Err(ConsoleError::InvalidValue(_)) => {
+                        root.set(&path, &value.round().to_string())
+                    }
+                    _ => result,
+                };
+                if let Err(e) = &*result {
+                    self.console.output.write_result(e.clone().into());
+                }
+            }
+        }
+        self.console
+            .record_changes(std::mem::replace(&mut root.changes, vec![]));
+    }
+
+    /// Draw a strip with a mark for each error or warning line, scaled to the whole buffer.
+    fn draw_overview(&mut self, ui: &imgui::Ui, footer_height: f32) {
+        let pos = ui.cursor_screen_pos();
+        let height = ui.content_region_avail()[1] - footer_height;
+        if height <= 0. {
+            return;
+        }
+        let clicked = ui.invisible_button(im_str!("overview"), [OVERVIEW_WIDTH, height]);
+
+        let mut marks = vec![];
+        let mut lines = 0;
+        for span in &self.console.output.buf {
+            if let Some(color) = severity_color(span.color) {
+                marks.push((lines, color));
+            }
+            if span.text.contains('\n') || !span.detail.is_empty() {
+                lines += 1;
+            }
+        }
+
+        let draw_list = ui.get_window_draw_list();
+        let right = pos[0] + OVERVIEW_WIDTH;
+        draw_list
+            .add_rect(pos, [right, pos[1] + height], [0., 0., 0., 0.3])
+            .filled(true)
+            .build();
+        for (line, color) in marks {
+            let y = pos[1] + height * line as f32 / lines.max(1) as f32;
+            draw_list
+                .add_rect([pos[0], y], [right, y + 2.], color)
+                .filled(true)
+                .build();
+        }
+
+        if clicked {
+            let fraction = (ui.io().mouse_pos[1] - pos[1]) / height;
+            self.scroll_to = Some(fraction.max(0.).min(1.));
+        }
+    }
+
+    /// Complete the last word of the prompt to a command or property name.
+    ///
+    /// Matching ignores case, but the word is always replaced with the name's real case.
+    /// If there is more than one candidate they are listed, best match first when fuzzy matching
+    /// is on, and the word is extended as far as they agree.
+    ///
+    /// Calling it again without editing the prompt cycles through the candidates in turn, Shift+Tab
+    /// in the window goes backwards.
+    pub fn complete_prompt(&mut self, root: &mut dyn IVisitExt) {
+        self.cycle_completion(root, false);
+    }
+
+    fn cycle_completion(&mut self, root: &mut dyn IVisitExt, backwards: bool) {
+        let prompt = self.prompt.to_string();
+        if let Some(state) = &mut self.completion {
+            if state.shown == prompt {
+                let len = state.candidates.len();
+                let index = match (state.index, backwards) {
+                    (None, false) => 0,
+                    (None, true) => len - 1,
+                    (Some(i), false) => (i + 1) % len,
+                    (Some(i), true) => (i + len - 1) % len,
+                };
+                state.index = Some(index);
+                state.shown = format!("{}{}", state.head, state.candidates[index]);
+                let shown = state.shown.clone();
+                self.set_prompt_buffer(&shown);
+                return;
+            }
+        }
+        self.completion = None;
+
+        let start = prompt.rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let mut commands = std::mem::replace(&mut self.console.commands, vec![]);
+        let mut root = VisitMutExt(|f, console| {
+            root.visit_mut_ext(f, console);
+            visit_commands(&mut commands, f, console);
+        });
+        // complete the value instead, for properties which only take a few
+        let word = &prompt[start..];
+        let values = match prompt.split_whitespace().next() {
+            Some(cmd) if start > 0 => root.values(cmd),
+            _ => vec![],
+        };
+        let candidates = if values.is_empty() && self.console.fuzzy {
+            root.complete_fuzzy(word)
+        } else if values.is_empty() {
+            root.complete(word)
+        } else {
+            let word = word.to_lowercase();
+            values
+                .into_iter()
+                .filter(|v| v.to_lowercase().starts_with(&word))
+                .collect()
+        };
+        self.console.commands = commands;
+
+        let head = &prompt[..start];
+        match candidates.len() {
+            0 => {}
+            1 => self.set_prompt_buffer(&format!("{}{} ", head, candidates[0])),
+            _ => {
+                self.writeln(candidates.join("  "));
+                // fuzzy candidates may agree on less than was typed, which is kept instead
+                let common = common_prefix(&candidates);
+                let common = if common.len() < word.len() {
+                    word
+                } else {
+                    common
+                };
+                let shown = format!("{}{}", head, common);
+                self.set_prompt_buffer(&shown);
+                self.completion = Some(Completion {
+                    head: head.to_string(),
+                    candidates,
+                    index: None,
+                    shown,
+                });
+            }
+        }
+    }
+
+    /// Put `text` in the prompt for the user to edit and run, and give it keyboard focus.
+    ///
+    /// Nothing is run. Anything already typed is replaced, and history browsing starts again
+    /// from the newest command.
+    pub fn set_prompt_text(&mut self, text: &str) {
+        self.set_prompt_buffer(text);
+        // a new id makes imgui read the text again, as it does when browsing history
+        self.prompt_id = !self.prompt_id;
+        self.history_pos = None;
+        self.completion = None;
+        self.search = None;
+        self.focus_prompt = true;
+    }
+
+    fn set_prompt_buffer(&mut self, text: &str) {
+        self.prompt.clear();
+        self.prompt.push_str(text);
+    }
+
+    /// Lines of text in the prompt, as many as it has room to show.
+    fn prompt_lines(&self) -> usize {
+        let lines = self.prompt.to_str().split('\n').count();
+        lines.max(1).min(MAX_PROMPT_LINES)
+    }
+
+    /*pub fn close(&mut self,) {
+        use cvar::IConsole;
+        console.write_error(&ConsoleError::Unimplemented);
+    }*/
+}
+
+/// How `watch` reads in the watch panel, with its value shown like `get` shows it.
+pub(crate) fn watch_label(watch: &Watch, format: &ValueFormat) -> String {
+    let value = watch
+        .value
+        .as_ref()
+        .map_or_else(|| "?".to_string(), |v| format.apply(v));
+    format!("{}: {}", watch.path, value)
+}
+
+/// The `[HH:MM:SS]` prefix for a line starting with `first`, counting from `started`, if enabled
+/// and known.
+fn timestamp(enabled: bool, started: Instant, first: &TextSpan) -> Option<String> {
+    if !enabled {
+        return None;
+    }
+    let time = first.time?;
+    let secs = if time > started {
+        (time - started).as_secs()
+    } else {
+        0
+    };
+    Some(format!(
+        "[{:02}:{:02}:{:02}] ",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    ))
+}
+
+/// Draw a run of spans, with collapsible ones as tree nodes. `id` keeps tree node ids unique.
+///
+/// Returns the command of any link clicked.
+fn draw_spans(ui: &imgui::Ui, spans: &[TextSpan], id: &str, wrap: bool) -> Option<String> {
+    let line_start = ui.cursor_pos()[0];
+    let mut clicked = None;
+    for (i, span) in spans.iter().enumerate() {
+        let link = if span.detail.is_empty() && wrap {
+            draw_span_wrapped(ui, span, line_start)
+        } else if span.detail.is_empty() {
+            draw_span(ui, span)
+        } else {
+            let id = format!("{}.{}", id, i);
+            let label = ImString::new(format!("{}##{}", span.text.trim_end(), id));
+            let color = ui.push_style_color(imgui::StyleColor::Text, span.color);
+            ui.tree_node(&label).build(|| {
+                if let Some(cmd) = draw_spans(ui, &span.detail, &id, wrap) {
+                    clicked = Some(cmd);
+                }
+            });
+            color.pop(ui);
+            false
+        };
+        if link {
+            clicked = span.command.clone();
+        }
+    }
+    end_line(ui, spans);
+    clicked
+}
+
+/// Returns whether the span was clicked, if it is a link.
+fn draw_span(ui: &imgui::Ui, span: &TextSpan) -> bool {
+    /*if span.text.contains("\r") {
+        let pos = ui.cursor_pos();
+        ui.set_cursor_pos([0., pos[1]]);
+    }*/
+    let clicked = draw_text(ui, span, &span.text);
+    if !span.text.contains('\n') {
+        ui.same_line(0.);
+    }
+    clicked
+}
+
+/// Draw `text` in the color of `span`, with its background and style.
+///
+/// Links are underlined while hovered. Returns whether one was clicked.
+fn draw_text(ui: &imgui::Ui, span: &TextSpan, text: &str) -> bool {
+    let link = span.command.is_some();
+    if span.bg.is_none() && span.style == SpanStyle::Normal && !link {
+        ui.text_colored(span.color, text);
+        return false;
+    }
+
+    // A trailing newline would make the box a line too tall
+    let size = ui.calc_text_size(&ImString::new(text.trim_end_matches('\n')), false, -1.);
+    let [left, top] = ui.cursor_screen_pos();
+    let draw_list = ui.get_window_draw_list();
+    if let Some(bg) = span.bg {
+        draw_list
+            .add_rect([left, top], [left + size[0], top + size[1]], bg)
+            .filled(true)
+            .build();
+    }
+    let start = ui.cursor_pos();
+    ui.text_colored(span.color, text);
+    match span.style {
+        SpanStyle::Normal => {}
+        SpanStyle::Bold => {
+            ui.set_cursor_pos([start[0] + 1., start[1]]);
+            ui.text_colored(span.color, text);
+        }
+        SpanStyle::Underline => {}
+    }
+    let hovered = link && ui.is_item_hovered();
+    if span.style == SpanStyle::Underline || hovered {
+        let bottom = top + size[1];
+        draw_list
+            .add_rect([left, bottom - 1.], [left + size[0], bottom], span.color)
+            .filled(true)
+            .build();
+    }
+    hovered && ui.is_mouse_clicked(imgui::MouseButton::Left)
+}
+
+/// Draw a span a word at a time, starting a new line whenever the next word doesn't fit.
+///
+/// `line_start` is where lines begin. A word too long for any line, such as a run of CJK text
+/// with no spaces, is split between characters instead. Everything is measured with the font, so
+/// wide and multi-byte characters take up the room they are drawn with.
+fn draw_span_wrapped(ui: &imgui::Ui, span: &TextSpan, line_start: f32) -> bool {
+    let mut clicked = false;
+    let mut lines = span.text.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let mut rest = line;
+        while !rest.is_empty() {
+            let end = rest.find(' ').map(|i| i + 1).unwrap_or_else(|| rest.len());
+            let (mut word, mut tail) = rest.split_at(end);
+
+            let width = ui.calc_text_size(&ImString::new(word), false, -1.)[0];
+            let mut avail = ui.content_region_avail()[0];
+            if width > avail && ui.cursor_pos()[0] > line_start {
+                ui.new_line();
+                avail = ui.content_region_avail()[0];
+            }
+            if width > avail {
+                let end = fitting_len(ui, word, avail);
+                word = &rest[..end];
+                tail = &rest[end..];
+            }
+            rest = tail;
+            clicked |= draw_text(ui, span, word);
+            ui.same_line(0.);
+        }
+        if lines.peek().is_some() {
+            ui.new_line();
+        }
+    }
+    clicked
+}
+
+/// Bytes at the start of `text` which fit in `width` when drawn, ending between two characters.
+///
+/// Always at least one character, so something is drawn even in a very narrow window.
+fn fitting_len(ui: &imgui::Ui, text: &str, width: f32) -> usize {
+    let mut fits = 0;
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        if fits > 0 && ui.calc_text_size(&ImString::new(&text[..end]), false, -1.)[0] > width {
+            break;
+        }
+        fits = end;
+    }
+    fits
+}
+
+/// Mark color for spans worth pointing out in the overview. Errors are red, warnings yellow.
+fn severity_color(color: [f32; 4]) -> Option<[f32; 4]> {
+    let [r, g, b, _] = color;
+    if r > 0.8 && g < 0.5 && b < 0.5 {
+        Some([1., 0.2, 0.2, 1.])
+    } else if r > 0.8 && g > 0.8 && b < 0.5 {
+        Some([1., 1., 0.2, 1.])
+    } else {
+        None
+    }
+}
+
+/// Finish a line left open by a trailing span without a newline (such as a partial write),
+/// so whatever is drawn next doesn't end up beside it.
+fn end_line(ui: &imgui::Ui, spans: &[TextSpan]) {
+    if let Some(span) = spans.last() {
+        if span.detail.is_empty() && !span.text.contains('\n') {
+            ui.new_line();
+        }
+    }
+}